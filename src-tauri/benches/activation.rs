@@ -0,0 +1,202 @@
+//! Benchmarks of the steps of an activation, run with `cargo bench`. Besides criterion's own reports, the
+//! durations measured by `info_timed` are written to `target/criterion/info_timed.json`.
+
+use app::cache::Cache;
+use app::image::{
+    get_cell_content, get_cells, get_layout, match_cell, process_mat, MatSync, SHORTLIST_LENGTH,
+};
+use app::logic::{
+    get_combo_value, get_produced_modifier_ids, suggest_custom_combo, LabeledCombo, ModifierId,
+    SolverSettings, UserSettings, MODIFIERS,
+};
+use app::utils::{get_timings, DiscSynchronized};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion};
+use itertools::Itertools;
+use opencv::imgcodecs::{imread, IMREAD_COLOR};
+use opencv::prelude::*;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{create_dir_all, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+
+const RESOURCES_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/resources");
+
+const TIMINGS_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/target/criterion/info_timed.json"
+);
+
+const ROSTER: [[&str; 4]; 2] = [
+    [
+        "Treant Horde",
+        "Evocationist",
+        "Frost Strider",
+        "Flame Strider",
+    ],
+    [
+        "Magma Barrier",
+        "Storm Strider",
+        "Corpse Detonator",
+        "Crystal-Skinned",
+    ],
+];
+
+fn get_modifier_id_by_name(modifier_name: &str) -> ModifierId {
+    MODIFIERS
+        .by_id
+        .values()
+        .find(|modifier| modifier.name == modifier_name)
+        .unwrap()
+        .id
+}
+
+fn load_screenshot() -> MatSync {
+    MatSync(imread(&format!("{}/screenshot.png", RESOURCES_PATH), IMREAD_COLOR).unwrap())
+}
+
+/// The modifiers of the stash on the first synthetic capture, in row-major order.
+fn load_stash_modifier_ids() -> Vec<ModifierId> {
+    let path = PathBuf::from(RESOURCES_PATH).join("synthetic_captures/manifest.json");
+    let manifest: serde_json::Value =
+        serde_json::from_reader(BufReader::new(File::open(path).unwrap())).unwrap();
+    manifest["screenshots"][0]["stash"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .flat_map(|row| row.as_array().unwrap())
+        .filter_map(|modifier_name| modifier_name.as_str())
+        .map(get_modifier_id_by_name)
+        .collect()
+}
+
+fn get_user_settings() -> UserSettings {
+    let combo_catalog = ROSTER
+        .iter()
+        .enumerate()
+        .map(|(index, combo)| {
+            LabeledCombo::new(
+                index as u64,
+                format!("Combo #{}", index),
+                combo
+                    .iter()
+                    .map(|&name| get_modifier_id_by_name(name))
+                    .collect(),
+            )
+        })
+        .collect_vec();
+    UserSettings {
+        combo_roster: combo_catalog.iter().map(|combo| combo.id).collect(),
+        combo_catalog,
+        forbidden_modifier_ids: BTreeSet::new(),
+        hotkey: "".into(),
+        show_tiers: false,
+        solver_settings: SolverSettings::default(),
+        manual_layout: None,
+    }
+}
+
+fn bench_layout(c: &mut Criterion) {
+    let screenshot = load_screenshot();
+    c.bench_function("get_layout/cold", |b| {
+        b.iter_batched(
+            Cache::create_new,
+            |mut cache| get_layout(&mut cache, &screenshot),
+            BatchSize::SmallInput,
+        )
+    });
+
+    let mut cache = Cache::create_new();
+    get_layout(&mut cache, &screenshot).unwrap();
+    c.bench_function("get_layout/cached", |b| {
+        b.iter(|| get_layout(&mut cache, &screenshot))
+    });
+}
+
+fn bench_modifier_id(c: &mut Criterion) {
+    let screenshot = load_screenshot();
+    let mut cache = Cache::create_new();
+    let layout = get_layout(&mut cache, &screenshot).unwrap();
+    let cell = get_cells(&layout)
+        .into_iter()
+        .find(|cell| cell.tag == 0)
+        .unwrap();
+    c.bench_function("get_cell_content/miss", |b| {
+        b.iter_batched(
+            Cache::create_new,
+            |cache| get_cell_content(&cache, &screenshot, &cell, false),
+            BatchSize::SmallInput,
+        )
+    });
+
+    get_cell_content(&cache, &screenshot, &cell, false);
+    c.bench_function("get_cell_content/hit", |b| {
+        b.iter(|| get_cell_content(&cache, &screenshot, &cell, false))
+    });
+
+    c.bench_function("match_cell/shortlist", |b| {
+        b.iter(|| match_cell(&screenshot, &cell, SHORTLIST_LENGTH))
+    });
+    c.bench_function("match_cell/exhaustive", |b| {
+        b.iter(|| match_cell(&screenshot, &cell, MODIFIERS.by_id.len()))
+    });
+}
+
+fn bench_process_image(c: &mut Criterion) {
+    let screenshot = load_screenshot();
+    c.bench_function("process_image/cold", |b| {
+        b.iter_batched(
+            || (Cache::create_new(), screenshot.try_clone().unwrap()),
+            |(mut cache, screenshot)| process_mat(&mut cache, screenshot, None),
+            BatchSize::LargeInput,
+        )
+    });
+
+    let mut cache = Cache::create_new();
+    c.bench_function("process_image/cached", |b| {
+        b.iter_batched(
+            || screenshot.try_clone().unwrap(),
+            |screenshot| process_mat(&mut cache, screenshot, None),
+            BatchSize::LargeInput,
+        )
+    });
+}
+
+fn bench_logic(c: &mut Criterion) {
+    let user_settings = get_user_settings();
+    let stash_modifier_ids = load_stash_modifier_ids();
+    let combo = user_settings.combo_catalog[0].combo.clone();
+    c.bench_function("get_combo_value", |b| {
+        b.iter(|| get_combo_value(black_box(&combo)))
+    });
+    c.bench_function("get_produced_modifier_ids", |b| {
+        b.iter(|| get_produced_modifier_ids(black_box(&stash_modifier_ids[..6])))
+    });
+
+    for stash_size in [32, 64] {
+        let stash: BTreeMap<ModifierId, usize> = stash_modifier_ids[..stash_size]
+            .iter()
+            .copied()
+            .counts()
+            .into_iter()
+            .collect();
+        c.bench_function(&format!("suggest_custom_combo/{}", stash_size), |b| {
+            b.iter(|| suggest_custom_combo(&user_settings, black_box(&stash), stash_size, &[]))
+        });
+    }
+}
+
+fn write_timings(_: &mut Criterion) {
+    let path = PathBuf::from(TIMINGS_PATH);
+    create_dir_all(path.parent().unwrap()).unwrap();
+    serde_json::to_writer_pretty(File::create(path).unwrap(), &get_timings()).unwrap();
+}
+
+criterion_group!(
+    benches,
+    bench_layout,
+    bench_modifier_id,
+    bench_process_image,
+    bench_logic,
+    write_timings
+);
+criterion_main!(benches);
@@ -1,3 +1,64 @@
+use data::{validate_modifiers, Modifier};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// shared with the app, the build script only reads part of it
+#[allow(dead_code)]
+mod data {
+    include!("src/data.rs");
+}
+
+const DATA_PATH: &str = "src/resources/data.json";
+
+/// The schema of the data, included above along with its checks.
+const DATA_SCHEMA_PATH: &str = "src/data.rs";
+
+const REFERENCE_IMAGES_PATH: &str = "src/resources/reference_images";
+
+/// Generated in the output directory, and included by `image.rs`.
+const MODIFIER_IMAGES_FILE_NAME: &str = "modifier_images.rs";
+
+/// Embeds the reference image of each modifier, so that adding a modifier only takes an entry in the data and
+/// an image.
+fn write_modifier_images(modifiers: &[Modifier]) -> std::io::Result<()> {
+    let reference_images_path =
+        PathBuf::from(env::var("CARGO_MANIFEST_DIR").unwrap()).join(REFERENCE_IMAGES_PATH);
+    let modifier_images = modifiers
+        .iter()
+        .map(|modifier| {
+            format!(
+                "    ({:?}, include_bytes!({:?})),\n",
+                modifier.name,
+                reference_images_path
+                    .join(format!("{}.png", modifier.name))
+                    .display()
+                    .to_string()
+            )
+        })
+        .collect::<String>();
+    fs::write(
+        PathBuf::from(env::var("OUT_DIR").unwrap()).join(MODIFIER_IMAGES_FILE_NAME),
+        format!("&[\n{}]\n", modifier_images),
+    )
+}
+
 fn main() {
+    println!("cargo:rerun-if-changed={}", DATA_PATH);
+    println!("cargo:rerun-if-changed={}", DATA_SCHEMA_PATH);
+    println!("cargo:rerun-if-changed={}", REFERENCE_IMAGES_PATH);
+
+    let data = fs::read_to_string(DATA_PATH)
+        .unwrap_or_else(|err| panic!("cannot read {}: {}", DATA_PATH, err));
+    let modifiers = serde_json::from_str::<Vec<Modifier>>(&data)
+        .unwrap_or_else(|err| panic!("invalid {}: {}", DATA_PATH, err));
+    let problems = validate_modifiers(&modifiers, Path::new(REFERENCE_IMAGES_PATH));
+    if !problems.is_empty() {
+        panic!("invalid {}:\n  - {}", DATA_PATH, problems.join("\n  - "));
+    }
+
+    write_modifier_images(&modifiers)
+        .unwrap_or_else(|err| panic!("cannot write the modifier images: {}", err));
+
     tauri_build::build()
 }
@@ -0,0 +1,23 @@
+#![no_main]
+
+use app::logic::{get_produced_modifier_ids, ModifierId, MODIFIERS, QUEUE_LENGTH};
+use libfuzzer_sys::fuzz_target;
+use std::collections::HashSet;
+
+// each byte is a modifier id, including unknown and duplicate ones
+fuzz_target!(|combo: &[ModifierId]| {
+    if combo.len() > QUEUE_LENGTH * 2 {
+        return;
+    }
+
+    let produced_modifier_ids = get_produced_modifier_ids(combo);
+
+    let mut used_modifier_ids = HashSet::new();
+    for (recipe, modifier_id) in &produced_modifier_ids {
+        assert_eq!(&MODIFIERS.by_id[modifier_id].recipe, recipe);
+        assert!(recipe.iter().all(|modifier_id| combo.contains(modifier_id)));
+        assert!(recipe
+            .iter()
+            .all(|&modifier_id| used_modifier_ids.insert(modifier_id)));
+    }
+});
@@ -0,0 +1,301 @@
+use crate::image::{get_detection_fingerprint, get_templates_fingerprint, CellContent, Vec2};
+use crate::logic::{get_data_fingerprint, get_solver_fingerprint, Suggestion};
+use crate::utils::{BincodeDiscSynchronized, DiscSynchronized};
+use dashmap::DashMap;
+use itertools::Itertools;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::hash_map::Entry as HashMapEntry;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{metadata, File};
+use std::io::{BufReader, BufWriter};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+pub const MAX_CACHED_IMAGES: usize = 4096;
+
+pub const MAX_CACHED_COMBOS: usize = 1024;
+
+/// Milliseconds since the epoch, strictly increasing between calls so that entries used within the same
+/// millisecond are still ordered.
+fn now() -> u64 {
+    static LAST_TIMESTAMP: AtomicU64 = AtomicU64::new(0);
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default();
+    let last_timestamp = LAST_TIMESTAMP
+        .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |last_timestamp| {
+            Some(timestamp.max(last_timestamp + 1))
+        })
+        .unwrap();
+    timestamp.max(last_timestamp + 1)
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CacheEntry<T> {
+    pub value: T,
+    pub created_at: u64,
+    pub last_used_at: u64,
+}
+
+impl<T> CacheEntry<T> {
+    fn new(value: T) -> Self {
+        let now = now();
+        Self {
+            value,
+            created_at: now,
+            last_used_at: now,
+        }
+    }
+
+    fn touch(&mut self) -> &T {
+        self.last_used_at = now();
+        &self.value
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct CacheCounters {
+    pub image_hits: AtomicU64,
+    pub image_misses: AtomicU64,
+    pub combo_hits: AtomicU64,
+    pub combo_misses: AtomicU64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheStats {
+    pub image_count: usize,
+    pub image_hits: u64,
+    pub image_misses: u64,
+    pub combo_count: usize,
+    pub combo_hits: u64,
+    pub combo_misses: u64,
+    pub size_on_disk: Option<u64>,
+}
+
+/// Fingerprints of everything the cached values were derived from, besides the app version.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprints {
+    pub data: u64,
+    pub templates: u64,
+    pub detection: u64,
+    pub solver: u64,
+}
+
+impl Fingerprints {
+    pub fn current() -> Self {
+        Self {
+            data: get_data_fingerprint(),
+            templates: get_templates_fingerprint(),
+            detection: get_detection_fingerprint(),
+            solver: get_solver_fingerprint(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Cache {
+    pub version: String,
+    pub fingerprints: Fingerprints,
+    pub modified: bool,
+    pub layout: Option<HashMap<u8, Vec2>>,
+    pub images: DashMap<u64, CacheEntry<CellContent>>,
+    pub suggested_combos: HashMap<u64, CacheEntry<Option<Suggestion>>>,
+    /// The last suggestion, with the key of the settings it was made for.
+    pub last_suggested_combo: Option<(u64, Suggestion)>,
+    /// Set by hits, which only update the recency of the entries. Unlike `modified`, it doesn't warrant a save
+    /// on every activation, but only on exit.
+    #[serde(skip)]
+    pub recency_modified: AtomicBool,
+    #[serde(skip)]
+    pub counters: CacheCounters,
+}
+
+/// Returns the keys of the least recently used entries, which have to be removed so that only `max_count`
+/// entries remain.
+fn get_evicted_keys<K: Copy>(entries: impl Iterator<Item = (K, u64)>, max_count: usize) -> Vec<K> {
+    let entries = entries.collect_vec();
+    let evicted_count = entries.len().saturating_sub(max_count);
+    entries
+        .into_iter()
+        .sorted_by_key(|&(_, last_used_at)| last_used_at)
+        .take(evicted_count)
+        .map(|(key, _)| key)
+        .collect()
+}
+
+impl Cache {
+    pub fn clear(&mut self) {
+        self.layout = None;
+        self.images.clear();
+        self.suggested_combos.clear();
+        self.last_suggested_combo = None;
+        self.modified = true;
+    }
+
+    /// Discards the sections whose fingerprints differ from the given ones.
+    pub fn invalidate_stale(&mut self, fingerprints: Fingerprints) {
+        if self.fingerprints == fingerprints {
+            return;
+        }
+
+        let data_changed = self.fingerprints.data != fingerprints.data;
+        let detection_changed = self.fingerprints.templates != fingerprints.templates
+            || self.fingerprints.detection != fingerprints.detection;
+        let solver_changed = self.fingerprints.solver != fingerprints.solver;
+        if detection_changed {
+            info!("discarding cached layout");
+            self.layout = None;
+        }
+
+        if data_changed || detection_changed {
+            info!("discarding cached images");
+            self.images.clear();
+        }
+
+        if data_changed || solver_changed {
+            info!("discarding cached combos");
+            self.suggested_combos.clear();
+            self.last_suggested_combo = None;
+        }
+
+        self.fingerprints = fingerprints;
+        self.modified = true;
+    }
+
+    /// Looks up the image with the given hash, or failing that, the one with the nearest hash within
+    /// `max_distance` differing bits, as long as `is_confirmed` accepts its content: similar hashes don't
+    /// guarantee the same content. Unlike [`Cache::get_or_insert_combo`], it cannot flag the cache as
+    /// modified on insertion, which is up to the caller.
+    pub fn get_or_insert_image(
+        &self,
+        key: u64,
+        max_distance: u32,
+        is_confirmed: impl FnOnce(CellContent) -> bool,
+        f: impl FnOnce() -> CellContent,
+    ) -> CellContent {
+        let nearest_key = if self.images.contains_key(&key) {
+            Some(key)
+        } else {
+            self.images
+                .iter()
+                .map(|entry| ((*entry.key() ^ key).count_ones(), *entry.key()))
+                .filter(|&(distance, _)| distance <= max_distance)
+                .min()
+                .map(|(_, key)| key)
+                .filter(|nearest_key| {
+                    // copied out, so that the entry isn't locked while its content is confirmed
+                    let content = self.images.get(nearest_key).map(|entry| entry.value);
+                    content.map_or(false, is_confirmed)
+                })
+        };
+        if let Some(mut entry) = nearest_key.and_then(|key| self.images.get_mut(&key)) {
+            self.counters.image_hits.fetch_add(1, Ordering::Relaxed);
+            self.recency_modified.store(true, Ordering::Relaxed);
+            return *entry.touch();
+        }
+
+        self.counters.image_misses.fetch_add(1, Ordering::Relaxed);
+        let value = f();
+        self.images.insert(key, CacheEntry::new(value));
+        value
+    }
+
+    pub fn get_or_insert_combo(
+        &mut self,
+        key: u64,
+        f: impl FnOnce() -> Option<Suggestion>,
+    ) -> Option<Suggestion> {
+        match self.suggested_combos.entry(key) {
+            HashMapEntry::Occupied(mut entry) => {
+                self.counters.combo_hits.fetch_add(1, Ordering::Relaxed);
+                self.recency_modified.store(true, Ordering::Relaxed);
+                entry.get_mut().touch().clone()
+            }
+            HashMapEntry::Vacant(entry) => {
+                self.counters.combo_misses.fetch_add(1, Ordering::Relaxed);
+                self.modified = true;
+                entry.insert(CacheEntry::new(f())).value.clone()
+            }
+        }
+    }
+
+    /// Drops the least recently used images and combos above the size limits.
+    pub fn evict(&mut self) {
+        let evicted_image_keys = get_evicted_keys(
+            self.images
+                .iter()
+                .map(|entry| (*entry.key(), entry.value().last_used_at)),
+            MAX_CACHED_IMAGES,
+        );
+        for key in &evicted_image_keys {
+            self.images.remove(key);
+        }
+
+        let evicted_combo_keys = get_evicted_keys(
+            self.suggested_combos
+                .iter()
+                .map(|(&key, entry)| (key, entry.last_used_at)),
+            MAX_CACHED_COMBOS,
+        );
+        for key in &evicted_combo_keys {
+            self.suggested_combos.remove(key);
+        }
+
+        if !evicted_image_keys.is_empty() || !evicted_combo_keys.is_empty() {
+            self.modified = true;
+        }
+    }
+
+    pub fn get_stats(&self) -> CacheStats {
+        CacheStats {
+            image_count: self.images.len(),
+            image_hits: self.counters.image_hits.load(Ordering::Relaxed),
+            image_misses: self.counters.image_misses.load(Ordering::Relaxed),
+            combo_count: self.suggested_combos.len(),
+            combo_hits: self.counters.combo_hits.load(Ordering::Relaxed),
+            combo_misses: self.counters.combo_misses.load(Ordering::Relaxed),
+            size_on_disk: Self::output_path()
+                .ok()
+                .and_then(|path| metadata(path).ok())
+                .map(|metadata| metadata.len()),
+        }
+    }
+}
+
+impl DiscSynchronized for Cache {
+    const FILE_NAME: &'static str = "archbroski\\.cache";
+
+    fn create_new() -> Self {
+        Self {
+            version: VERSION.into(),
+            fingerprints: Fingerprints::current(),
+            modified: false,
+            layout: None,
+            images: DashMap::new(),
+            suggested_combos: HashMap::new(),
+            last_suggested_combo: None,
+            recency_modified: AtomicBool::new(false),
+            counters: CacheCounters::default(),
+        }
+    }
+
+    fn save_impl(&self, writer: &mut BufWriter<File>) -> Result<(), Box<dyn Error>> {
+        <Self as BincodeDiscSynchronized>::save_impl(self, writer)
+    }
+
+    fn is_valid(&self) -> bool {
+        self.version == VERSION
+    }
+
+    fn load_impl(reader: BufReader<File>) -> Result<Self, Box<dyn Error>> {
+        <Self as BincodeDiscSynchronized>::load_impl(reader)
+    }
+}
+
+impl BincodeDiscSynchronized for Cache {}
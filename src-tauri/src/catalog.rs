@@ -0,0 +1,301 @@
+use crate::logic::{ComboId, LabeledCombo, ModifierId, UserSettings, MODIFIERS, QUEUE_LENGTH};
+use crate::utils::{DiscSynchronized, InstanceLock};
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::Read;
+use thiserror::Error;
+
+pub const MAX_CATALOG_SIZE: usize = 8;
+
+const SHARE_STRING_PREFIX: &str = "archbroski:";
+
+const COMBO_SEPARATOR: char = '|';
+
+const LABEL_SEPARATOR: char = ':';
+
+const MODIFIER_SEPARATOR: char = ',';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ShareFormat {
+    Compact,
+    Json,
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum ShareError {
+    #[error("unknown combo id: {0}")]
+    UnknownComboId(ComboId),
+    #[error("malformed combo: {0:?}")]
+    MalformedCombo(String),
+    #[error("malformed json: {0}")]
+    MalformedJson(String),
+    #[error("unknown modifier: {0:?}")]
+    UnknownModifier(String),
+    #[error("unknown modifier id: {0}")]
+    UnknownModifierId(ModifierId),
+    #[error("combo {0:?} must have exactly {} distinct modifiers", QUEUE_LENGTH)]
+    InvalidCombo(String),
+    #[error("nothing to import")]
+    Empty,
+    #[error("the catalog cannot hold more than {} combos", MAX_CATALOG_SIZE)]
+    CatalogFull,
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SharedCombo {
+    label: String,
+    combo: Vec<String>,
+}
+
+fn escape(value: &str) -> String {
+    value.chars().fold(String::new(), |mut escaped, c| {
+        if matches!(
+            c,
+            '%' | COMBO_SEPARATOR | LABEL_SEPARATOR | MODIFIER_SEPARATOR
+        ) {
+            escaped.push_str(&format!("%{:02X}", c as u32));
+        } else {
+            escaped.push(c);
+        }
+
+        escaped
+    })
+}
+
+fn unescape(value: &str) -> Result<String, ShareError> {
+    let mut unescaped = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c == '%' {
+            let code = chars.by_ref().take(2).collect::<String>();
+            let c = u32::from_str_radix(&code, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .filter(|_| code.len() == 2)
+                .ok_or_else(|| ShareError::MalformedCombo(value.to_owned()))?;
+            unescaped.push(c);
+        } else {
+            unescaped.push(c);
+        }
+    }
+
+    Ok(unescaped)
+}
+
+fn get_modifier_id(modifier_name: &str) -> Result<ModifierId, ShareError> {
+    let modifier_name = modifier_name.trim();
+    MODIFIERS
+        .by_id
+        .values()
+        .find(|modifier| modifier.name.eq_ignore_ascii_case(modifier_name))
+        .map(|modifier| modifier.id)
+        .ok_or_else(|| ShareError::UnknownModifier(modifier_name.to_owned()))
+}
+
+fn parse_compact(data: &str) -> Result<Vec<SharedCombo>, ShareError> {
+    data.trim_start_matches(SHARE_STRING_PREFIX)
+        .split(COMBO_SEPARATOR)
+        .map(str::trim)
+        .filter(|combo| !combo.is_empty())
+        .map(|combo| {
+            let (label, modifier_names) = combo
+                .split_once(LABEL_SEPARATOR)
+                .ok_or_else(|| ShareError::MalformedCombo(combo.to_owned()))?;
+            Ok(SharedCombo {
+                label: unescape(label)?,
+                combo: modifier_names
+                    .split(MODIFIER_SEPARATOR)
+                    .map(unescape)
+                    .collect::<Result<_, _>>()?,
+            })
+        })
+        .collect()
+}
+
+fn parse(data: &str) -> Result<Vec<SharedCombo>, ShareError> {
+    let data = data.trim();
+    if data.starts_with('[') {
+        serde_json::from_str(data).map_err(|err| ShareError::MalformedJson(err.to_string()))
+    } else {
+        parse_compact(data)
+    }
+}
+
+/// Serializes the given combos, referring to modifiers by name, so that the result is independent of
+/// the modifier ids.
+pub fn export_combos(
+    user_settings: &UserSettings,
+    combo_ids: &[ComboId],
+    format: ShareFormat,
+) -> Result<String, ShareError> {
+    let shared_combos = combo_ids
+        .iter()
+        .map(|&combo_id| {
+            user_settings
+                .combo_catalog
+                .iter()
+                .find(|combo| combo.id == combo_id)
+                .ok_or(ShareError::UnknownComboId(combo_id))
+                .and_then(|combo| {
+                    Ok(SharedCombo {
+                        label: combo.label.clone(),
+                        combo: combo
+                            .combo
+                            .iter()
+                            .map(|modifier_id| {
+                                MODIFIERS
+                                    .by_id
+                                    .get(modifier_id)
+                                    .map(|modifier| modifier.name.clone())
+                                    .ok_or(ShareError::UnknownModifierId(*modifier_id))
+                            })
+                            .collect::<Result<_, _>>()?,
+                    })
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    match format {
+        ShareFormat::Compact => Ok(format!(
+            "{}{}",
+            SHARE_STRING_PREFIX,
+            shared_combos
+                .iter()
+                .map(|shared_combo| {
+                    format!(
+                        "{}{}{}",
+                        escape(&shared_combo.label),
+                        LABEL_SEPARATOR,
+                        shared_combo
+                            .combo
+                            .iter()
+                            .map(|modifier_name| escape(modifier_name))
+                            .join(&MODIFIER_SEPARATOR.to_string())
+                    )
+                })
+                .join(&COMBO_SEPARATOR.to_string())
+        )),
+        ShareFormat::Json => serde_json::to_string_pretty(&shared_combos)
+            .map_err(|err| ShareError::MalformedJson(err.to_string())),
+    }
+}
+
+/// Parses combos exported by [`export_combos`] (in either format), and appends them to the catalog with
+/// freshly assigned ids. Either all of the combos are imported, or none of them.
+pub fn import_combos(
+    user_settings: &mut UserSettings,
+    data: &str,
+) -> Result<Vec<ComboId>, ShareError> {
+    let shared_combos = parse(data)?;
+    if shared_combos.is_empty() {
+        return Err(ShareError::Empty);
+    }
+
+    if user_settings.combo_catalog.len() + shared_combos.len() > MAX_CATALOG_SIZE {
+        return Err(ShareError::CatalogFull);
+    }
+
+    let combos = shared_combos
+        .into_iter()
+        .map(|shared_combo| {
+            let combo = shared_combo
+                .combo
+                .iter()
+                .map(|modifier_name| get_modifier_id(modifier_name))
+                .collect::<Result<Vec<_>, _>>()?;
+            if combo.len() != QUEUE_LENGTH || combo.iter().duplicates().count() > 0 {
+                return Err(ShareError::InvalidCombo(shared_combo.label));
+            }
+
+            Ok((shared_combo.label, combo))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let next_combo_id = user_settings
+        .combo_catalog
+        .iter()
+        .map(|combo| combo.id + 1)
+        .max()
+        .unwrap_or_default();
+    let imported_combo_ids = combos
+        .into_iter()
+        .enumerate()
+        .map(|(index, (label, combo))| {
+            let combo_id = next_combo_id + index as ComboId;
+            user_settings
+                .combo_catalog
+                .push(LabeledCombo::new(combo_id, label, combo));
+            combo_id
+        })
+        .collect_vec();
+    Ok(imported_combo_ids)
+}
+
+/// Handles the `export` and `import` subcommands, returning the exit code if any of them was run.
+pub fn run_cli(args: &[String]) -> Option<i32> {
+    let result = match args {
+        [command, rest @ ..] if command == "export" => UserSettings::load()
+            .map_err(|err| err.to_string())
+            .and_then(|user_settings| {
+                let format = if rest.iter().any(|arg| arg == "--json") {
+                    ShareFormat::Json
+                } else {
+                    ShareFormat::Compact
+                };
+                let combo_ids = rest
+                    .iter()
+                    .filter(|&arg| arg != "--json")
+                    .map(|arg| arg.parse::<ComboId>().map_err(|err| err.to_string()))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let combo_ids = if combo_ids.is_empty() {
+                    user_settings
+                        .combo_catalog
+                        .iter()
+                        .map(|combo| combo.id)
+                        .collect()
+                } else {
+                    combo_ids
+                };
+                export_combos(&user_settings, &combo_ids, format).map_err(|err| err.to_string())
+            }),
+        // the running app would overwrite the imported combos with its own settings
+        [command, path] if command == "import" => InstanceLock::acquire()
+            .map_err(|err| {
+                format!(
+                    "cannot import while the app is running ({}), import from the catalog tab instead",
+                    err
+                )
+            })
+            .and_then(|_instance_lock| {
+                let mut data = String::new();
+                if path == "-" {
+                    std::io::stdin().read_to_string(&mut data)
+                } else {
+                    File::open(path).and_then(|mut file| file.read_to_string(&mut data))
+                }
+                .map_err(|err| err.to_string())
+                .and_then(|_| UserSettings::load_or_new_saved().map_err(|err| err.to_string()))
+                .and_then(|mut user_settings| {
+                    let combo_ids =
+                        import_combos(&mut user_settings, &data).map_err(|err| err.to_string())?;
+                    user_settings.save().map_err(|err| err.to_string())?;
+                    Ok(format!("imported combos: {:?}", combo_ids))
+                })
+            }),
+        [command, ..] if command == "import" => {
+            Err("usage: archbroski import <path or ->".to_owned())
+        }
+        _ => return None,
+    };
+    match result {
+        Ok(output) => {
+            println!("{}", output);
+            Some(0)
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            Some(1)
+        }
+    }
+}
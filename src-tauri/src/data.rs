@@ -0,0 +1,169 @@
+// The schema of the modifier data, and the checks it has to pass. The build script includes this file to
+// validate the data before it is embedded, so it may only depend on `std` and `serde`.
+
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::Path;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Reward {
+    Generic,
+    Armour,
+    Weapon,
+    Jewelry,
+    Gem,
+    Map,
+    DivinationCard,
+    Fragment,
+    Essence,
+    Harbinger,
+    Unique,
+    Delve,
+    Blight,
+    Ritual,
+    Currency,
+    Legion,
+    Breach,
+    Labyrinth,
+    Scarab,
+    Abyss,
+    Heist,
+    Expedition,
+    Delirium,
+    Metamorph,
+    Treant,
+}
+
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Effect {
+    Reroll { count: usize },
+    AdditionalReward,
+    DoubledReward,
+    Convert { to: Reward },
+}
+
+pub type ModifierId = u8;
+
+#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Modifier {
+    pub id: ModifierId,
+    pub name: String,
+    pub recipe: BTreeSet<ModifierId>,
+    #[serde(skip_serializing)]
+    pub rewards: HashMap<Reward, usize>,
+    #[serde(skip_serializing)]
+    pub effect: Option<Effect>,
+}
+
+/// Finds a recipe cycle through the given modifier, which would make the computation of the components of the
+/// modifiers loop forever.
+pub fn find_cycle(
+    modifier_id: ModifierId,
+    recipes: &HashMap<ModifierId, &BTreeSet<ModifierId>>,
+    path: &mut Vec<ModifierId>,
+    acyclic_modifier_ids: &mut BTreeSet<ModifierId>,
+) -> Option<Vec<ModifierId>> {
+    if acyclic_modifier_ids.contains(&modifier_id) {
+        return None;
+    }
+
+    if let Some(index) = path.iter().position(|&id| id == modifier_id) {
+        let mut cycle = path[index..].to_vec();
+        cycle.push(modifier_id);
+        return Some(cycle);
+    }
+
+    path.push(modifier_id);
+    let cycle = recipes
+        .get(&modifier_id)
+        .into_iter()
+        .flat_map(|recipe| recipe.iter())
+        .find_map(|&component_id| find_cycle(component_id, recipes, path, acyclic_modifier_ids));
+    path.pop();
+    if cycle.is_none() {
+        acyclic_modifier_ids.insert(modifier_id);
+    }
+
+    cycle
+}
+
+/// Checks everything the app takes for granted about the modifier data, returning the problems found.
+pub fn validate_modifiers(modifiers: &[Modifier], reference_images_path: &Path) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    let mut names = BTreeMap::new();
+    for modifier in modifiers {
+        if let Some(other_id) = names.insert(modifier.name.as_str(), modifier.id) {
+            problems.push(format!(
+                "modifiers {} and {} are both named {:?}",
+                other_id, modifier.id, modifier.name
+            ));
+        }
+    }
+
+    let mut recipes = HashMap::new();
+    for modifier in modifiers {
+        if recipes.insert(modifier.id, &modifier.recipe).is_some() {
+            problems.push(format!("more than one modifier has the id {}", modifier.id));
+        }
+    }
+
+    let mut modifier_ids_by_recipe = BTreeMap::new();
+    for modifier in modifiers {
+        for component_id in &modifier.recipe {
+            if !recipes.contains_key(component_id) {
+                problems.push(format!(
+                    "the recipe of {:?} references the unknown modifier {}",
+                    modifier.name, component_id
+                ));
+            }
+        }
+
+        if !modifier.recipe.is_empty() {
+            if let Some(other_id) = modifier_ids_by_recipe.insert(&modifier.recipe, modifier.id) {
+                problems.push(format!(
+                    "modifiers {} and {} have the same recipe {:?}",
+                    other_id, modifier.id, modifier.recipe
+                ));
+            }
+        }
+
+        if !reference_images_path
+            .join(format!("{}.png", modifier.name))
+            .is_file()
+        {
+            problems.push(format!(
+                "{:?} has no reference image in {}",
+                modifier.name,
+                reference_images_path.display()
+            ));
+        }
+    }
+
+    let mut acyclic_modifier_ids = BTreeSet::new();
+    for modifier in modifiers {
+        if let Some(cycle) = find_cycle(
+            modifier.id,
+            &recipes,
+            &mut Vec::new(),
+            &mut acyclic_modifier_ids,
+        ) {
+            let names_by_id = modifiers
+                .iter()
+                .map(|modifier| (modifier.id, modifier.name.as_str()))
+                .collect::<HashMap<_, _>>();
+            problems.push(format!(
+                "recipe cycle: {}",
+                cycle
+                    .iter()
+                    .map(|modifier_id| format!("{:?}", names_by_id[modifier_id]))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ));
+            break;
+        }
+    }
+
+    problems
+}
@@ -1,36 +1,72 @@
-use crate::logic::{ModifierId, MODIFIERS};
-use crate::{collection, info_timed, Cache};
+use crate::cache::Cache;
+use crate::inventory::StashCell;
+use crate::logic::{Modifier, ModifierId, MODIFIERS};
+use crate::utils::{stable_hash, StableHasher};
+use crate::{collection, info_timed};
 use dashmap::DashMap;
+use dirs::config_dir;
 use itertools::Itertools;
 use log::{info, warn};
 use once_cell::sync::Lazy;
-use opencv::core::{min_max_loc, Point, Scalar, CV_32F, CV_8U};
-use opencv::imgcodecs::IMREAD_COLOR;
-use opencv::imgproc::{cvt_color, COLOR_BGR2GRAY, COLOR_BGRA2BGR};
+use opencv::core::{mean_std_dev, min_max_loc, Point, Rect, CV_32F, CV_8U};
+use opencv::imgcodecs::{IMREAD_COLOR, IMREAD_GRAYSCALE};
+use opencv::imgproc::{
+    cvt_color, resize, COLOR_BGR2GRAY, COLOR_BGRA2BGR, INTER_AREA, INTER_LINEAR,
+};
 use opencv::prelude::*;
 use opencv::{
     core::{Mat, MatExprTraitConst, MatTraitConstManual, Range, Size, Vector},
-    imgcodecs::imdecode,
+    imgcodecs::{imdecode, imencode},
     imgproc::{match_template as opencv_match_template, TM_CCOEFF_NORMED},
 };
 use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::cmp::Ordering::Equal;
-use std::collections::hash_map::DefaultHasher;
-use std::collections::HashSet;
-use std::hash::{Hash, Hasher};
+use std::collections::{BTreeMap, HashSet};
+use std::error::Error;
+use std::fs::{create_dir_all, read, remove_dir_all, write};
+use std::io::Write;
+use std::path::PathBuf;
 use std::{collections::HashMap, ops::Deref};
 
-macro_rules! import_images {
-    ($($s:expr),*) => {
-      collection![$(
-        $s => include_bytes!(concat!("resources/reference_images/", $s, ".png")).to_vec(),
-      )*]
-    };
-}
+const LAYOUT_MATCH_THRESHOLD: f32 = 0.95;
+
+const MODIFIER_MATCH_THRESHOLD: f32 = 0.8;
+
+const CELL_PADDING: u32 = 10;
+
+const IMAGE_HASH_SIZE: i32 = 8;
+
+/// Cells whose hashes differ in at most this many bits are considered identical, once the cached content is
+/// confirmed against the cell. Kept below the distance between the hashes of any two modifier templates.
+pub const MAX_IMAGE_HASH_DISTANCE: u32 = 3;
+
+/// Cells whose grayscale image, without its padding, has a lower standard deviation are considered empty.
+const EMPTY_CELL_MAX_STD_DEV: f64 = 16.0;
+
+/// Number of bins of the histogram of each channel.
+const HISTOGRAM_BIN_COUNT: usize = 8;
+
+/// Number of modifiers whose histograms are the closest to a cell's, which are then matched against it.
+pub const SHORTLIST_LENGTH: usize = 8;
 
-#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+/// Templates captured from the user's own screen, named after their modifier.
+const USER_TEMPLATES_DIRECTORY: &str = "archbroski\\templates";
+
+/// Templates of the digits of stack counts, named after their digit, in the user templates directory.
+const DIGIT_TEMPLATES_DIRECTORY: &str = "digits";
+
+const DIGIT_MATCH_THRESHOLD: f32 = 0.8;
+
+const MIN_LAYOUT_SCALE_PERCENT: u32 = 50;
+
+const MAX_LAYOUT_SCALE_PERCENT: u32 = 200;
+
+/// UI scales tried when looking for layout candidates during a calibration.
+const LAYOUT_CALIBRATION_SCALE_PERCENTS: [u32; 7] = [75, 80, 90, 100, 110, 120, 125];
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Vec2 {
     pub x: u32,
     pub y: u32,
@@ -40,6 +76,13 @@ impl Vec2 {
     pub fn new(x: u32, y: u32) -> Self {
         Self { x, y }
     }
+
+    pub fn scale(&self, factor: f32) -> Vec2 {
+        Vec2::new(
+            (self.x as f32 * factor).round() as u32,
+            (self.y as f32 * factor).round() as u32,
+        )
+    }
 }
 
 #[derive(
@@ -70,10 +113,16 @@ impl Rectangle {
             self.height,
         )
     }
+
+    pub fn scale(&self, factor: f32) -> Rectangle {
+        let position = Vec2::new(self.x, self.y).scale(factor);
+        let size = Vec2::new(self.width, self.height).scale(factor);
+        Rectangle::new(position.x, position.y, size.x, size.y)
+    }
 }
 
 /// https://github.com/twistedfall/opencv-rust/issues/324
-struct MatSync(Mat);
+pub struct MatSync(pub Mat);
 
 unsafe impl Sync for MatSync {}
 
@@ -85,17 +134,61 @@ impl Deref for MatSync {
     }
 }
 
+/// What a cell holds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CellContent {
+    Empty,
+    Modifier(ModifierId),
+    /// Neither empty nor matching any template, e.g. a modifier hidden by a tooltip.
+    Unknown,
+}
+
+impl CellContent {
+    pub fn get_modifier_id(&self) -> Option<ModifierId> {
+        match self {
+            CellContent::Modifier(modifier_id) => Some(*modifier_id),
+            CellContent::Empty | CellContent::Unknown => None,
+        }
+    }
+}
+
 #[derive(Debug)]
-struct Cell {
-    tag: u8,
-    area: Rectangle,
+pub struct Cell {
+    pub tag: u8,
+    pub area: Rectangle,
+}
+
+/// What the cells of a group are part of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CellGroupRole {
+    Stash,
+    Queue,
 }
 
 struct CellGroup {
     template: MatSync,
     grayscale: bool,
+    role: CellGroupRole,
     area: Rectangle,
     cell_areas: HashSet<Rectangle>,
+    /// Where the stack count is drawn in each cell, relative to the cell, if the group shows stack counts.
+    count_area: Option<Rectangle>,
+}
+
+impl CellGroup {
+    /// Size of the area covered by the template and the cells, from the offset of the group.
+    fn get_extent(&self) -> Vec2 {
+        let template_size = self.template.size().unwrap();
+        self.cell_areas.iter().fold(
+            Vec2::new(template_size.width as u32, template_size.height as u32),
+            |extent, cell_area| {
+                Vec2::new(
+                    extent.x.max(cell_area.x + cell_area.width),
+                    extent.y.max(cell_area.y + cell_area.height),
+                )
+            },
+        )
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -117,8 +210,58 @@ impl Screenshot {
 
 pub struct ProcessImageResult {
     pub stash_area: Rectangle,
-    pub stash_modifier_ids: HashMap<Rectangle, Option<ModifierId>>,
-    pub queue_modifier_ids: Vec<Option<ModifierId>>,
+    pub stash_cells: HashMap<Rectangle, StashCell>,
+    pub queue_contents: Vec<CellContent>,
+    pub layout: HashMap<u8, Vec2>,
+}
+
+/// Position of each cell group set by the user, for screens where the templates don't match well enough, e.g.
+/// because of a different UI scale.
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ManualLayout {
+    /// Offset of each cell group in the screenshot, by tag.
+    pub offsets: BTreeMap<u8, Vec2>,
+    /// Size of the UI relative to the templates, in percent.
+    pub scale_percent: u32,
+}
+
+impl ManualLayout {
+    pub fn get_scale(&self) -> f32 {
+        self.scale_percent as f32 / 100.0
+    }
+
+    pub fn is_valid(&self) -> bool {
+        (MIN_LAYOUT_SCALE_PERCENT..=MAX_LAYOUT_SCALE_PERCENT).contains(&self.scale_percent)
+            && CELL_GROUPS
+                .iter()
+                .all(|entry| self.offsets.contains_key(entry.key()))
+    }
+}
+
+/// Best position found for a cell group during a layout calibration.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutCandidate {
+    pub tag: u8,
+    /// Area covered by the cell group, in the screenshot.
+    pub area: Rectangle,
+    pub score: f32,
+}
+
+/// A screenshot along with the candidate position of each cell group, so that the user can confirm or fix them.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LayoutCalibration {
+    /// The screenshot, as a JPEG image.
+    pub image: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// UI scale at which the candidates matched best, in percent.
+    pub scale_percent: u32,
+    pub candidates: Vec<LayoutCandidate>,
+    /// Score above which a candidate is detected automatically.
+    pub threshold: f32,
 }
 
 static CELL_GROUPS: Lazy<DashMap<u8, CellGroup>> = Lazy::new(|| {
@@ -145,6 +288,7 @@ static CELL_GROUPS: Lazy<DashMap<u8, CellGroup>> = Lazy::new(|| {
         CellGroup {
             template: MatSync(template),
             grayscale: false,
+            role: CellGroupRole::Stash,
             area: Rectangle::new(
                 offset.0,
                 offset.1,
@@ -152,6 +296,13 @@ static CELL_GROUPS: Lazy<DashMap<u8, CellGroup>> = Lazy::new(|| {
                 template_size.height as u32,
             ),
             cell_areas,
+            // the game draws stack sizes in the top left corner of the cell
+            count_area: Some(Rectangle::new(
+                0,
+                0,
+                (cell_size_f32 / 2.0).floor() as u32,
+                (cell_size_f32 / 3.0).floor() as u32,
+            )),
         }
     };
     let queue = {
@@ -170,8 +321,10 @@ static CELL_GROUPS: Lazy<DashMap<u8, CellGroup>> = Lazy::new(|| {
         CellGroup {
             template: MatSync(template),
             grayscale: true,
+            role: CellGroupRole::Queue,
             area: Rectangle::new(0, 0, 0, 0),
             cell_areas,
+            count_area: None,
         }
     };
     collection![
@@ -180,140 +333,341 @@ static CELL_GROUPS: Lazy<DashMap<u8, CellGroup>> = Lazy::new(|| {
     ]
 });
 
-static MODIFIER_TEMPLATES: Lazy<DashMap<&str, (MatSync, MatSync)>> = Lazy::new(|| {
-    let modifier_templates: HashMap<_, _> = import_images!(
-        "Abberath-Touched",
-        "Arakaali-Touched",
-        "Arcane Buffer",
-        "Assassin",
-        "Berserker",
-        "Bloodletter",
-        "Bombardier",
-        "Bonebreaker",
-        "Brine King-Touched",
-        "Chaosweaver",
-        "Consecrator",
-        "Corpse Detonator",
-        "Corrupter",
-        "Crystal-Skinned",
-        "Deadeye",
-        "Drought Bringer",
-        "Dynamo",
-        "Echoist",
-        "Effigy",
-        "Empowered Elements",
-        "Empowering Minions",
-        "Entangler",
-        "Evocationist",
-        "Executioner",
-        "Flame Strider",
-        "Flameweaver",
-        "Frenzied",
-        "Frost Strider",
-        "Frostweaver",
-        "Gargantuan",
-        "Hasted",
-        "Heralding Minions",
-        "Hexer",
-        "Ice Prison",
-        "Incendiary",
-        "Innocence-Touched",
-        "Invulnerable",
-        "Juggernaut",
-        "Kitava-Touched",
-        "Lunaris-Touched",
-        "Magma Barrier",
-        "Malediction",
-        "Mana Siphoner",
-        "Mirror Image",
-        "Necromancer",
-        "Opulent",
-        "Overcharged",
-        "Permafrost",
-        "Rejuvenating",
-        "Sentinel",
-        "Shakari-Touched",
-        "Solaris-Touched",
-        "Soul Conduit",
-        "Soul Eater",
-        "Steel-Infused",
-        "Storm Strider",
-        "Stormweaver",
-        "Temporal Bubble",
-        "Toxic",
-        "Treant Horde",
-        "Trickster",
-        "Tukohama-Touched",
-        "Vampiric"
-    );
-    modifier_templates
-        .into_iter()
-        .map(|(modifier_name, modifier_template)| {
+/// Reference image of each modifier, embedded by the build script from the modifier data.
+const MODIFIER_IMAGES: &[(&str, &[u8])] = include!(concat!(env!("OUT_DIR"), "/modifier_images.rs"));
+
+/// The template of a modifier, along with the histograms used to shortlist it.
+pub struct ModifierTemplate {
+    template: MatSync,
+    template_grayscale: MatSync,
+    histogram: Vec<f32>,
+    histogram_grayscale: Vec<f32>,
+}
+
+impl ModifierTemplate {
+    pub fn new(template: Mat) -> Self {
+        let template_grayscale = to_grayscale(&template);
+        Self {
+            histogram: get_histogram(&template),
+            histogram_grayscale: get_histogram(&template_grayscale),
+            template: MatSync(template),
+            template_grayscale: MatSync(template_grayscale),
+        }
+    }
+
+    fn get_template(&self, grayscale: bool) -> &Mat {
+        if grayscale {
+            &self.template_grayscale
+        } else {
+            &self.template
+        }
+    }
+
+    fn get_histogram(&self, grayscale: bool) -> &[f32] {
+        if grayscale {
+            &self.histogram_grayscale
+        } else {
+            &self.histogram
+        }
+    }
+}
+
+static MODIFIER_TEMPLATES: Lazy<DashMap<&str, ModifierTemplate>> = Lazy::new(|| {
+    MODIFIER_IMAGES
+        .iter()
+        .map(|&(modifier_name, modifier_image)| {
             let modifier_template =
-                imdecode(&Vector::from_slice(&modifier_template), IMREAD_COLOR).unwrap();
-            let modifier_template_grayscale = to_grayscale(&modifier_template);
-            (
-                modifier_name,
-                (
-                    MatSync(modifier_template),
-                    MatSync(modifier_template_grayscale),
-                ),
-            )
+                imdecode(&Vector::from_slice(modifier_image), IMREAD_COLOR).unwrap();
+            (modifier_name, ModifierTemplate::new(modifier_template))
         })
         .collect()
 });
 
+/// Preferred over the built-in templates, e.g. to cope with post-processing filters. Empty until the app loads
+/// them with [`reload_user_templates`], so that the tests only use the built-in templates.
+static USER_TEMPLATES: Lazy<DashMap<ModifierId, ModifierTemplate>> = Lazy::new(DashMap::new);
+
+/// Templates of the digits of stack counts, captured by the user since none are built in. Loaded along with the
+/// user templates; without them, each stash cell counts as a single modifier.
+static DIGIT_TEMPLATES: Lazy<DashMap<usize, MatSync>> = Lazy::new(DashMap::new);
+
+/// A cell captured to calibrate the modifier templates, along with the modifier recognized in it, if any.
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CalibrationCell {
+    pub tag: u8,
+    pub area: Rectangle,
+    pub modifier_id: Option<ModifierId>,
+    /// The cell without its padding, as a PNG image.
+    pub image: Vec<u8>,
+}
+
+fn get_user_templates_path() -> Result<PathBuf, Box<dyn Error>> {
+    config_dir()
+        .ok_or_else(|| "Cannot find home directory.".into())
+        .map(|path| path.join(USER_TEMPLATES_DIRECTORY))
+}
+
+fn load_user_templates(user_templates: &DashMap<ModifierId, ModifierTemplate>) {
+    user_templates.clear();
+    let user_templates_path = match get_user_templates_path() {
+        Ok(user_templates_path) => user_templates_path,
+        Err(_) => return,
+    };
+
+    for modifier in MODIFIERS.by_id.values() {
+        let path = user_templates_path.join(format!("{}.png", modifier.name));
+        if !path.is_file() {
+            continue;
+        }
+
+        match read(&path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|image| {
+                imdecode(&Vector::from_slice(&image), IMREAD_COLOR).map_err(Into::into)
+            }) {
+            Ok(template) if !template.empty() => {
+                user_templates.insert(modifier.id, ModifierTemplate::new(template));
+            }
+            _ => warn!("invalid user template: {:?}", path),
+        }
+    }
+}
+
+fn load_digit_templates(digit_templates: &DashMap<usize, MatSync>) {
+    digit_templates.clear();
+    let digit_templates_path = match get_user_templates_path() {
+        Ok(user_templates_path) => user_templates_path.join(DIGIT_TEMPLATES_DIRECTORY),
+        Err(_) => return,
+    };
+
+    for digit in 0..10 {
+        let path = digit_templates_path.join(format!("{}.png", digit));
+        if !path.is_file() {
+            continue;
+        }
+
+        match read(&path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|image| {
+                imdecode(&Vector::from_slice(&image), IMREAD_GRAYSCALE).map_err(Into::into)
+            }) {
+            Ok(template) if !template.empty() => {
+                digit_templates.insert(digit, MatSync(template));
+            }
+            _ => warn!("invalid digit template: {:?}", path),
+        }
+    }
+}
+
+/// Reads the user templates, at startup and after a calibration.
+pub fn reload_user_templates() {
+    load_user_templates(&USER_TEMPLATES);
+    load_digit_templates(&DIGIT_TEMPLATES);
+    info!(
+        "loaded {} user templates and {} digit templates",
+        USER_TEMPLATES.len(),
+        DIGIT_TEMPLATES.len()
+    );
+}
+
+pub fn get_user_template_modifier_ids() -> Vec<ModifierId> {
+    USER_TEMPLATES
+        .iter()
+        .map(|entry| *entry.key())
+        .sorted()
+        .collect()
+}
+
+/// Stores the images of the given cells as the templates of their modifiers, replacing any previous ones. A
+/// modifier can only be given one template at a time.
+pub fn save_user_templates<'a>(
+    templates: impl IntoIterator<Item = (ModifierId, &'a CalibrationCell)>,
+) -> Result<(), Box<dyn Error>> {
+    let templates = templates.into_iter().collect_vec();
+    if let Some(modifier_id) = templates
+        .iter()
+        .map(|(modifier_id, _)| modifier_id)
+        .duplicates()
+        .next()
+    {
+        return Err(format!(
+            "more than one cell is labeled as {}",
+            MODIFIERS.by_id[modifier_id].name
+        )
+        .into());
+    }
+
+    let user_templates_path = get_user_templates_path()?;
+    create_dir_all(&user_templates_path)?;
+    for (modifier_id, cell) in templates {
+        write(
+            user_templates_path.join(format!("{}.png", MODIFIERS.by_id[&modifier_id].name)),
+            &cell.image,
+        )?;
+    }
+
+    reload_user_templates();
+    Ok(())
+}
+
+/// Reverts to the built-in templates.
+pub fn clear_user_templates() -> Result<(), Box<dyn Error>> {
+    let user_templates_path = get_user_templates_path()?;
+    if user_templates_path.is_dir() {
+        remove_dir_all(&user_templates_path)?;
+    }
+
+    reload_user_templates();
+    Ok(())
+}
+
 fn to_grayscale(image: &Mat) -> Mat {
     let mut image_grayscale = Mat::default();
     cvt_color(&image, &mut image_grayscale, COLOR_BGR2GRAY, 0).unwrap();
     image_grayscale
 }
 
-fn crop_image(image: &Mat, padding: u32) -> Mat {
-    let image_size = image.size().unwrap();
-    let image = image.clone();
-    let crop = |image: &Mat, x_range: &Range, y_range: &Range| {
-        Mat::rowscols(image, y_range, x_range)
-            .unwrap()
-            .set(Scalar::new(0.0, 0.0, 0.0, 0.0))
-            .unwrap();
+fn crop_padding(cell_image: &Mat) -> Mat {
+    let cell_image_size = cell_image.size().unwrap();
+    Mat::roi(
+        cell_image,
+        Rect::new(
+            CELL_PADDING as i32,
+            CELL_PADDING as i32,
+            cell_image_size.width - 2 * CELL_PADDING as i32,
+            cell_image_size.height - 2 * CELL_PADDING as i32,
+        ),
+    )
+    .unwrap()
+}
+
+/// Difference hash of the image: each bit tells whether a pixel of the downscaled image is brighter than its
+/// right neighbour. Unlike a hash over the raw bytes, it is stable across builds and similar images produce
+/// similar hashes.
+fn get_difference_hash(image_grayscale: &Mat) -> u64 {
+    let mut image_resized = Mat::default();
+    resize(
+        image_grayscale,
+        &mut image_resized,
+        Size::new(IMAGE_HASH_SIZE + 1, IMAGE_HASH_SIZE),
+        0.0,
+        0.0,
+        INTER_AREA,
+    )
+    .unwrap();
+    (0..IMAGE_HASH_SIZE)
+        .cartesian_product(0..IMAGE_HASH_SIZE)
+        .fold(0u64, |hash, (y, x)| {
+            let left = *image_resized.at_2d::<u8>(y, x).unwrap();
+            let right = *image_resized.at_2d::<u8>(y, x + 1).unwrap();
+            (hash << 1) | (left < right) as u64
+        })
+}
+
+/// Difference hash of the cell, ignoring its padding.
+fn hash_image(image_grayscale: &Mat) -> u64 {
+    get_difference_hash(&crop_padding(image_grayscale))
+}
+
+/// Difference hashes of the built-in modifier templates.
+pub fn get_template_hashes() -> BTreeMap<ModifierId, u64> {
+    MODIFIERS
+        .by_id
+        .values()
+        .map(|modifier| {
+            let modifier_template = MODIFIER_TEMPLATES.get(modifier.name.as_str()).unwrap();
+            (
+                modifier.id,
+                get_difference_hash(&modifier_template.template_grayscale),
+            )
+        })
+        .collect()
+}
+
+/// Normalized histogram of each channel, a cheap descriptor which doesn't depend on where the modifier is in
+/// the image.
+fn get_histogram(image: &Mat) -> Vec<f32> {
+    let image = if image.is_continuous() {
+        Cow::Borrowed(image)
+    } else {
+        Cow::Owned(image.try_clone().unwrap())
     };
-    crop(
-        &image,
-        &Range::new(0, padding as i32).unwrap(),
-        &Range::new(0, image_size.height).unwrap(),
-    );
-    crop(
-        &image,
-        &Range::new(image_size.width - padding as i32, image_size.width).unwrap(),
-        &Range::new(0, image_size.height).unwrap(),
-    );
-    crop(
-        &image,
-        &Range::new(0, image_size.width).unwrap(),
-        &Range::new(0, padding as i32).unwrap(),
-    );
-    crop(
-        &image,
-        &Range::new(0, image_size.width).unwrap(),
-        &Range::new(image_size.height - padding as i32, image_size.height).unwrap(),
-    );
-    image
+
+    let channel_count = image.channels() as usize;
+    let data = image.data_bytes().unwrap();
+    let pixel_count = (data.len() / channel_count) as f32;
+    data.iter().enumerate().fold(
+        vec![0.0; channel_count * HISTOGRAM_BIN_COUNT],
+        |mut histogram, (index, &value)| {
+            histogram[index % channel_count * HISTOGRAM_BIN_COUNT
+                + value as usize * HISTOGRAM_BIN_COUNT / 256] += 1.0 / pixel_count;
+            histogram
+        },
+    )
 }
 
-fn hash_image(image: &Mat) -> u64 {
+fn get_histogram_distance(histogram1: &[f32], histogram2: &[f32]) -> f32 {
+    histogram1
+        .iter()
+        .zip(histogram2)
+        .map(|(bin1, bin2)| (bin1 - bin2).abs())
+        .sum()
+}
+
+fn stable_hash_image(image: &Mat) -> u64 {
     let image = if image.is_continuous() {
         Cow::Borrowed(image)
     } else {
         Cow::Owned(image.try_clone().unwrap())
     };
 
-    let mut hasher = DefaultHasher::new();
-    image.data_bytes().unwrap().hash(&mut hasher);
+    let mut hasher = StableHasher::default();
+    hasher.write_all(image.data_bytes().unwrap()).unwrap();
     hasher.finish()
 }
 
-fn match_template(source: &Mat, template: &Mat) -> (Vec2, f32) {
+/// Fingerprint of the layout and modifier templates (including the user ones); any change invalidates the
+/// cached layout and images.
+pub fn get_templates_fingerprint() -> u64 {
+    let cell_group_hashes = CELL_GROUPS
+        .iter()
+        .map(|entry| (*entry.key(), stable_hash_image(&entry.template)))
+        .sorted()
+        .collect_vec();
+    let modifier_template_hashes = MODIFIER_TEMPLATES
+        .iter()
+        .map(|entry| (entry.key().to_string(), stable_hash_image(&entry.template)))
+        .sorted()
+        .collect_vec();
+    let user_template_hashes = USER_TEMPLATES
+        .iter()
+        .map(|entry| (*entry.key(), stable_hash_image(&entry.template)))
+        .sorted()
+        .collect_vec();
+    stable_hash(&(
+        cell_group_hashes,
+        modifier_template_hashes,
+        user_template_hashes,
+    ))
+}
+
+/// Fingerprint of the detection parameters; any change invalidates the cached layout and images.
+pub fn get_detection_fingerprint() -> u64 {
+    stable_hash(&(
+        LAYOUT_MATCH_THRESHOLD.to_bits(),
+        MODIFIER_MATCH_THRESHOLD.to_bits(),
+        CELL_PADDING,
+        IMAGE_HASH_SIZE,
+        MAX_IMAGE_HASH_DISTANCE,
+        EMPTY_CELL_MAX_STD_DEV.to_bits(),
+        HISTOGRAM_BIN_COUNT,
+        SHORTLIST_LENGTH,
+    ))
+}
+
+/// Score of the template at each of its offsets in the source.
+fn get_match_scores(source: &Mat, template: &Mat) -> Mat {
     let source_size = source.size().unwrap();
     let source_width = source_size.width;
     let source_height = source_size.height;
@@ -337,7 +691,11 @@ fn match_template(source: &Mat, template: &Mat) -> (Vec2, f32) {
         &Mat::ones_size(template.size().unwrap(), CV_8U).unwrap(),
     )
     .unwrap();
+    results
+}
 
+fn match_template(source: &Mat, template: &Mat) -> (Vec2, f32) {
+    let results = get_match_scores(source, template);
     let max_val = &mut 0f64;
     let max_loc = &mut Point::new(0, 0);
     min_max_loc(
@@ -355,12 +713,39 @@ fn match_template(source: &Mat, template: &Mat) -> (Vec2, f32) {
     )
 }
 
-fn get_layout(cache: &mut Cache, screenshot: &MatSync) -> Option<HashMap<u8, Vec2>> {
+/// Resizes the screenshot so that its UI has the size of the templates.
+fn unscale_screenshot(screenshot: Mat, scale: f32) -> Mat {
+    if scale == 1.0 {
+        return screenshot;
+    }
+
+    let mut screenshot_unscaled = Mat::default();
+    resize(
+        &screenshot,
+        &mut screenshot_unscaled,
+        Size::default(),
+        1.0 / scale as f64,
+        1.0 / scale as f64,
+        if scale > 1.0 {
+            INTER_AREA
+        } else {
+            INTER_LINEAR
+        },
+    )
+    .unwrap();
+    screenshot_unscaled
+}
+
+/// Finds the offsets of the cell groups, reusing the cached ones if they all still match.
+pub fn get_layout(cache: &mut Cache, screenshot: &MatSync) -> Option<HashMap<u8, Vec2>> {
     let layout_matches = |layout: &&HashMap<u8, Vec2>| {
         CELL_GROUPS.par_iter_mut().all(|entry| {
             let tag = *entry.key();
             let cell_group = &*entry;
-            let cell_group_offset = layout[&tag];
+            let cell_group_offset = match layout.get(&tag) {
+                Some(&cell_group_offset) => cell_group_offset,
+                None => return false,
+            };
             let cell_group_template_size = cell_group.template.size().unwrap();
             let source = Mat::rowscols(
                 screenshot,
@@ -376,24 +761,27 @@ fn get_layout(cache: &mut Cache, screenshot: &MatSync) -> Option<HashMap<u8, Vec
                 .unwrap(),
             )
             .unwrap();
-            match_template(&source, &cell_group.template).1 > 0.95
+            match_template(&source, &cell_group.template).1 > LAYOUT_MATCH_THRESHOLD
         })
     };
+    let find_cell_groups = || -> HashMap<u8, Vec2> {
+        CELL_GROUPS
+            .par_iter_mut()
+            .filter_map(|entry| {
+                let tag = *entry.key();
+                let cell_group = &*entry;
+                let (offset, score) = match_template(screenshot, &cell_group.template);
+                if score.is_normal() && score > LAYOUT_MATCH_THRESHOLD {
+                    Some((tag, offset))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    };
     if cache.layout.as_ref().filter(layout_matches).is_none() {
         if let Some(layout) = {
-            let layout: HashMap<_, _> = CELL_GROUPS
-                .par_iter_mut()
-                .filter_map(|entry| {
-                    let tag = *entry.key();
-                    let cell_group = &*entry;
-                    let (offset, score) = match_template(screenshot, &cell_group.template);
-                    if score.is_normal() && score > 0.95 {
-                        Some((tag, offset))
-                    } else {
-                        None
-                    }
-                })
-                .collect();
+            let layout = find_cell_groups();
             if layout.len() < CELL_GROUPS.len() {
                 None
             } else {
@@ -414,116 +802,529 @@ fn get_layout(cache: &mut Cache, screenshot: &MatSync) -> Option<HashMap<u8, Vec
     }
 }
 
-fn get_cells(layout: &HashMap<u8, Vec2>) -> Vec<Cell> {
+/// The layout of a screenshot unscaled by the scale of the manual layout, if all its cells are in the screenshot.
+fn get_manual_layout(
+    screenshot: &MatSync,
+    manual_layout: &ManualLayout,
+) -> Option<HashMap<u8, Vec2>> {
+    if !manual_layout.is_valid() {
+        warn!("invalid manual layout");
+        return None;
+    }
+
+    let layout = manual_layout
+        .offsets
+        .iter()
+        .map(|(&tag, offset)| (tag, offset.scale(1.0 / manual_layout.get_scale())))
+        .collect::<HashMap<_, _>>();
+    let screenshot_size = screenshot.size().unwrap();
+    if get_cells(&layout).iter().all(|cell| {
+        cell.area.x + cell.area.width <= screenshot_size.width as u32
+            && cell.area.y + cell.area.height <= screenshot_size.height as u32
+    }) {
+        info!("using manual layout");
+        Some(layout)
+    } else {
+        warn!("manual layout out of the screenshot");
+        None
+    }
+}
+
+/// Unscales the screenshot by the scale of the manual layout if there is one, and finds its layout.
+fn get_screenshot_layout(
+    cache: &mut Cache,
+    screenshot: Mat,
+    manual_layout: Option<&ManualLayout>,
+) -> (MatSync, Option<HashMap<u8, Vec2>>) {
+    match manual_layout {
+        Some(manual_layout) => {
+            let screenshot = MatSync(unscale_screenshot(screenshot, manual_layout.get_scale()));
+            let layout = get_manual_layout(&screenshot, manual_layout);
+            (screenshot, layout)
+        }
+        None => {
+            let screenshot = MatSync(screenshot);
+            let layout = info_timed!("get_layout", get_layout(cache, &screenshot));
+            (screenshot, layout)
+        }
+    }
+}
+
+/// Looks for the best position of each cell group at several UI scales, keeping the scale at which they match
+/// best overall.
+pub fn get_layout_calibration(screenshot: Screenshot) -> LayoutCalibration {
+    let screenshot = screenshot.into_mat();
+    let (scale_percent, candidates) = LAYOUT_CALIBRATION_SCALE_PERCENTS
+        .iter()
+        .map(|&scale_percent| {
+            let scale = scale_percent as f32 / 100.0;
+            let screenshot_unscaled =
+                MatSync(unscale_screenshot(screenshot.try_clone().unwrap(), scale));
+            let screenshot_size = screenshot_unscaled.size().unwrap();
+            let candidates = CELL_GROUPS
+                .par_iter_mut()
+                .map(|entry| {
+                    let tag = *entry.key();
+                    let cell_group = &*entry;
+                    let template_size = cell_group.template.size().unwrap();
+                    let (offset, score) = if template_size.width <= screenshot_size.width
+                        && template_size.height <= screenshot_size.height
+                    {
+                        match_template(&screenshot_unscaled, &cell_group.template)
+                    } else {
+                        (Vec2::default(), 0.0)
+                    };
+                    let extent = cell_group.get_extent();
+                    LayoutCandidate {
+                        tag,
+                        area: Rectangle::new(offset.x, offset.y, extent.x, extent.y).scale(scale),
+                        score: if score.is_normal() { score } else { 0.0 },
+                    }
+                })
+                .collect::<Vec<_>>();
+            (scale_percent, candidates)
+        })
+        .max_by(|(_, candidates1), (_, candidates2)| {
+            let score1 = candidates1
+                .iter()
+                .map(|candidate| candidate.score)
+                .sum::<f32>();
+            let score2 = candidates2
+                .iter()
+                .map(|candidate| candidate.score)
+                .sum::<f32>();
+            score1.partial_cmp(&score2).unwrap_or(Equal)
+        })
+        .unwrap();
+
+    let mut image = Vector::new();
+    imencode(".jpg", &screenshot, &mut image, &Vector::new()).unwrap();
+    let screenshot_size = screenshot.size().unwrap();
+    LayoutCalibration {
+        image: image.to_vec(),
+        width: screenshot_size.width as u32,
+        height: screenshot_size.height as u32,
+        scale_percent,
+        candidates: candidates
+            .into_iter()
+            .sorted_by_key(|candidate| candidate.tag)
+            .collect(),
+        threshold: LAYOUT_MATCH_THRESHOLD,
+    }
+}
+
+/// The cells of the cell groups in the layout.
+pub fn get_cells(layout: &HashMap<u8, Vec2>) -> Vec<Cell> {
     CELL_GROUPS
         .iter()
-        .flat_map(|entry| {
+        .filter_map(|entry| {
             let tag = *entry.key();
             let cell_group = &*entry;
-            let cell_group_offset = layout[&tag];
-            cell_group
-                .cell_areas
-                .iter()
-                .map(|&cell_area| Cell {
-                    tag,
-                    area: cell_area.translate(cell_group_offset),
-                })
-                .collect_vec()
+            layout.get(&tag).map(|&cell_group_offset| {
+                cell_group
+                    .cell_areas
+                    .iter()
+                    .map(|&cell_area| Cell {
+                        tag,
+                        area: cell_area.translate(cell_group_offset),
+                    })
+                    .collect_vec()
+            })
         })
+        .flatten()
         .collect_vec()
 }
 
-fn get_modifier_id(
-    cache_images: &DashMap<u64, Option<ModifierId>>,
-    screenshot: &Mat,
-    cell: &Cell,
-    grayscale: bool,
-) -> Option<ModifierId> {
-    let cell_image = Mat::rowscols(
+fn get_cell_image(screenshot: &Mat, cell: &Cell) -> Mat {
+    Mat::rowscols(
         screenshot,
         &Range::new(cell.area.y as i32, (cell.area.y + cell.area.height) as i32).unwrap(),
         &Range::new(cell.area.x as i32, (cell.area.x + cell.area.width) as i32).unwrap(),
     )
+    .unwrap()
+}
+
+/// The user template of a modifier if it fits in the cell, or else its built-in template.
+pub fn choose_modifier_template<'a>(
+    user_template: Option<&'a ModifierTemplate>,
+    template: &'a ModifierTemplate,
+    cell_image_size: Size,
+) -> &'a ModifierTemplate {
+    user_template
+        .filter(|user_template| {
+            let template_size = user_template.template.size().unwrap();
+            template_size.width <= cell_image_size.width
+                && template_size.height <= cell_image_size.height
+        })
+        .unwrap_or(template)
+}
+
+/// Calls `f` with the template of the modifier, preferring its user template if it fits in the cell.
+fn with_modifier_template<T>(
+    modifier: &Modifier,
+    cell_image_size: Size,
+    f: impl FnOnce(&ModifierTemplate) -> T,
+) -> T {
+    let user_template = USER_TEMPLATES.get(&modifier.id);
+    let template = MODIFIER_TEMPLATES.get(modifier.name.as_str()).unwrap();
+    f(choose_modifier_template(
+        user_template.as_deref(),
+        &template,
+        cell_image_size,
+    ))
+}
+
+/// Shortlists the `shortlist_length` modifiers whose histograms are the closest to the cell's, then matches
+/// the cell against their templates.
+fn match_modifier(
+    cell_image: &Mat,
+    cell_image_grayscale: &Mat,
+    grayscale: bool,
+    shortlist_length: usize,
+) -> Option<ModifierId> {
+    let cell_image = if grayscale {
+        cell_image_grayscale
+    } else {
+        cell_image
+    };
+    let cell_image_size = cell_image.size().unwrap();
+    let cell_histogram = get_histogram(&crop_padding(cell_image));
+    let (modifier_id, score) = MODIFIERS
+        .by_id
+        .values()
+        .map(|modifier| {
+            let distance = with_modifier_template(modifier, cell_image_size, |modifier_template| {
+                get_histogram_distance(&cell_histogram, modifier_template.get_histogram(grayscale))
+            });
+            (modifier, distance)
+        })
+        .sorted_by(|&(_, distance1), &(_, distance2)| {
+            distance1.partial_cmp(&distance2).unwrap_or(Equal)
+        })
+        .take(shortlist_length)
+        .map(|(modifier, _)| {
+            let score = with_modifier_template(modifier, cell_image_size, |modifier_template| {
+                match_template(cell_image, modifier_template.get_template(grayscale)).1
+            });
+            (modifier.id, score)
+        })
+        .max_by(|&(_, score1), &(_, score2)| score1.partial_cmp(&score2).unwrap_or(Equal))
+        .unwrap();
+    if score.is_normal() && score > MODIFIER_MATCH_THRESHOLD {
+        Some(modifier_id)
+    } else {
+        None
+    }
+}
+
+/// An empty slot is nearly uniform, unlike any modifier.
+fn is_empty_cell(cell_image_grayscale: &Mat) -> bool {
+    let mut mean = Mat::default();
+    let mut std_dev = Mat::default();
+    mean_std_dev(
+        &crop_padding(cell_image_grayscale),
+        &mut mean,
+        &mut std_dev,
+        &Mat::default(),
+    )
     .unwrap();
+    *std_dev.at::<f64>(0).unwrap() < EMPTY_CELL_MAX_STD_DEV
+}
+
+/// Whether the cell shows the given content, cached for a cell with a similar hash.
+fn is_content_confirmed(
+    cell_image: &Mat,
+    cell_image_grayscale: &Mat,
+    grayscale: bool,
+    content: CellContent,
+) -> bool {
+    match content {
+        CellContent::Empty => is_empty_cell(cell_image_grayscale),
+        CellContent::Modifier(modifier_id) => {
+            let cell_image = if grayscale {
+                cell_image_grayscale
+            } else {
+                cell_image
+            };
+            MODIFIERS.by_id.get(&modifier_id).map_or(false, |modifier| {
+                let score = with_modifier_template(
+                    modifier,
+                    cell_image.size().unwrap(),
+                    |modifier_template| {
+                        match_template(cell_image, modifier_template.get_template(grayscale)).1
+                    },
+                );
+                score.is_normal() && score > MODIFIER_MATCH_THRESHOLD
+            })
+        }
+        // any modifier could match it, which is as expensive as recognizing the cell again
+        CellContent::Unknown => false,
+    }
+}
+
+fn get_content(
+    cell_image: &Mat,
+    cell_image_grayscale: &Mat,
+    grayscale: bool,
+    shortlist_length: usize,
+) -> CellContent {
+    if is_empty_cell(cell_image_grayscale) {
+        return CellContent::Empty;
+    }
+
+    match match_modifier(
+        cell_image,
+        cell_image_grayscale,
+        grayscale,
+        shortlist_length,
+    ) {
+        Some(modifier_id) => CellContent::Modifier(modifier_id),
+        None => CellContent::Unknown,
+    }
+}
+
+/// Recognizes the content of the cell without the cache, matching it against the templates of the
+/// `shortlist_length` modifiers whose histograms are the closest to the cell's.
+pub fn match_cell(screenshot: &Mat, cell: &Cell, shortlist_length: usize) -> CellContent {
+    let grayscale = CELL_GROUPS.get(&cell.tag).unwrap().grayscale;
+    let cell_image = get_cell_image(screenshot, cell);
+    get_content(
+        &cell_image,
+        &to_grayscale(&cell_image),
+        grayscale,
+        shortlist_length,
+    )
+}
+
+pub fn get_cell_content(
+    cache: &Cache,
+    screenshot: &Mat,
+    cell: &Cell,
+    grayscale: bool,
+) -> CellContent {
+    let cell_image = get_cell_image(screenshot, cell);
     let cell_image_grayscale = to_grayscale(&cell_image);
-    let modifier_id = *cache_images
-        .entry(hash_image(&crop_image(&cell_image, 10)))
-        .or_insert_with(|| {
-            let (modifier_id, score) = MODIFIERS
-                .by_id
-                .values()
-                .map(|modifier| {
-                    let (template, template_grayscale) =
-                        &*MODIFIER_TEMPLATES.get(modifier.name.as_str()).unwrap();
-                    (
-                        modifier.id,
-                        match_template(
-                            if grayscale {
-                                &cell_image_grayscale
-                            } else {
-                                &cell_image
-                            },
-                            if grayscale {
-                                template_grayscale
-                            } else {
-                                template
-                            },
-                        )
-                        .1,
-                    )
+    let image_hash = hash_image(&cell_image_grayscale);
+    cache.get_or_insert_image(
+        image_hash,
+        MAX_IMAGE_HASH_DISTANCE,
+        |content| is_content_confirmed(&cell_image, &cell_image_grayscale, grayscale, content),
+        || {
+            get_content(
+                &cell_image,
+                &cell_image_grayscale,
+                grayscale,
+                SHORTLIST_LENGTH,
+            )
+        },
+    )
+}
+
+/// Reads the stack count drawn in the count area of the cell, or `None` if no digit template matches it. Digits
+/// are read from left to right, each one being the best match among the overlapping ones.
+pub fn read_count(
+    cell_image_grayscale: &Mat,
+    count_area: Rectangle,
+    digit_templates: &DashMap<usize, MatSync>,
+) -> Option<usize> {
+    let count_image = Mat::rowscols(
+        cell_image_grayscale,
+        &Range::new(
+            count_area.y as i32,
+            (count_area.y + count_area.height) as i32,
+        )
+        .unwrap(),
+        &Range::new(
+            count_area.x as i32,
+            (count_area.x + count_area.width) as i32,
+        )
+        .unwrap(),
+    )
+    .unwrap();
+    let count_image_size = count_image.size().unwrap();
+    let digit_matches = digit_templates
+        .iter()
+        .filter(|entry| {
+            let template_size = entry.size().unwrap();
+            template_size.width <= count_image_size.width
+                && template_size.height <= count_image_size.height
+        })
+        .flat_map(|entry| {
+            let digit = *entry.key();
+            let digit_width = entry.size().unwrap().width;
+            let scores = get_match_scores(&count_image, entry.value());
+            let scores_size = scores.size().unwrap();
+            // the best score of the digit in each column
+            (0..scores_size.width)
+                .filter_map(|x| {
+                    let score = (0..scores_size.height)
+                        .map(|y| *scores.at_2d::<f32>(y, x).unwrap())
+                        .fold(f32::MIN, f32::max);
+                    if score.is_normal() && score > DIGIT_MATCH_THRESHOLD {
+                        Some((x, digit_width, digit, score))
+                    } else {
+                        None
+                    }
                 })
-                .max_by(|&(_, score1), &(_, score2)| score1.partial_cmp(&score2).unwrap_or(Equal))
-                .unwrap();
-            if score.is_normal() && score > 0.8 {
-                Some(modifier_id)
-            } else {
-                None
-            }
+                .collect_vec()
+        })
+        .sorted_by(|&(_, _, _, score1), &(_, _, _, score2)| {
+            score2.partial_cmp(&score1).unwrap_or(Equal)
+        })
+        .fold(
+            Vec::<(i32, i32, usize)>::new(),
+            |mut digits, (x, digit_width, digit, _)| {
+                // matches less than half a digit apart are the same digit
+                if digits.iter().all(|&(other_x, other_digit_width, _)| {
+                    (x - other_x).abs() * 2 >= digit_width.min(other_digit_width)
+                }) {
+                    digits.push((x, digit_width, digit));
+                }
+
+                digits
+            },
+        );
+    if digit_matches.is_empty() {
+        None
+    } else {
+        Some(
+            digit_matches
+                .into_iter()
+                .sorted_by_key(|&(x, _, _)| x)
+                .fold(0, |count, (_, _, digit)| count * 10 + digit),
+        )
+    }
+}
+
+/// Number of modifiers stacked in the cell, read from its stack count if its group shows them.
+fn get_count(screenshot: &Mat, cell: &Cell, count_area: Option<Rectangle>) -> usize {
+    count_area
+        .and_then(|count_area| {
+            read_count(
+                &to_grayscale(&get_cell_image(screenshot, cell)),
+                count_area,
+                &DIGIT_TEMPLATES,
+            )
         })
-        .value();
-    modifier_id
+        .filter(|&count| count > 0)
+        .unwrap_or(1)
 }
 
-pub fn process_image(cache: &mut Cache, screenshot: Screenshot) -> Option<ProcessImageResult> {
-    let screenshot = MatSync(screenshot.into_mat());
-    info_timed!("get_layout", get_layout(cache, &screenshot)).map(|layout| {
+/// Captures every non-empty cell of the stash and the queue, so that the user can label them and use them as
+/// templates.
+pub fn get_calibration_cells(
+    cache: &mut Cache,
+    screenshot: Screenshot,
+    manual_layout: Option<&ManualLayout>,
+) -> Option<Vec<CalibrationCell>> {
+    let scale = manual_layout.map_or(1.0, ManualLayout::get_scale);
+    let (screenshot, layout) = get_screenshot_layout(cache, screenshot.into_mat(), manual_layout);
+    layout.map(|layout| {
+        get_cells(&layout)
+            .into_par_iter()
+            .filter_map(|cell| {
+                let grayscale = CELL_GROUPS.get(&cell.tag).unwrap().grayscale;
+                let cell_image = get_cell_image(&screenshot, &cell);
+                let cell_image_grayscale = to_grayscale(&cell_image);
+                if is_empty_cell(&cell_image_grayscale) {
+                    return None;
+                }
+
+                let mut image = Vector::new();
+                imencode(
+                    ".png",
+                    &crop_padding(&cell_image),
+                    &mut image,
+                    &Vector::new(),
+                )
+                .unwrap();
+                Some(CalibrationCell {
+                    tag: cell.tag,
+                    area: cell.area.scale(scale),
+                    modifier_id: match_modifier(
+                        &cell_image,
+                        &cell_image_grayscale,
+                        grayscale,
+                        SHORTLIST_LENGTH,
+                    ),
+                    image: image.to_vec(),
+                })
+            })
+            .collect()
+    })
+}
+
+/// Detects the modifiers of the stash and the queue, at the manual layout if there is one. The areas of the
+/// result are in the screenshot either way.
+pub fn process_image(
+    cache: &mut Cache,
+    screenshot: Screenshot,
+    manual_layout: Option<&ManualLayout>,
+) -> Option<ProcessImageResult> {
+    process_mat(cache, screenshot.into_mat(), manual_layout)
+}
+
+/// Same as [`process_image`], but for a screenshot which is already a BGR image.
+pub fn process_mat(
+    cache: &mut Cache,
+    screenshot: Mat,
+    manual_layout: Option<&ManualLayout>,
+) -> Option<ProcessImageResult> {
+    let scale = manual_layout.map_or(1.0, ManualLayout::get_scale);
+    let (screenshot, layout) = get_screenshot_layout(cache, screenshot, manual_layout);
+    layout.map(|layout| {
         let cells = get_cells(&layout);
-        let cache_images = &cache.images;
-        let cache_images_count = cache_images.len();
-        let modifier_ids = info_timed!(
+        let cache_images_count = cache.images.len();
+        let cell_contents = info_timed!(
             "match_cells",
             cells
                 .into_par_iter()
                 .map(|cell| {
-                    let grayscale = CELL_GROUPS.get(&cell.tag).unwrap().grayscale;
-                    (
-                        cell.tag,
-                        cell.area,
-                        get_modifier_id(cache_images, &screenshot, &cell, grayscale),
-                    )
+                    let (grayscale, role, count_area) = {
+                        let cell_group = CELL_GROUPS.get(&cell.tag).unwrap();
+                        (cell_group.grayscale, cell_group.role, cell_group.count_area)
+                    };
+                    let content = get_cell_content(cache, &screenshot, &cell, grayscale);
+                    let count = match content {
+                        CellContent::Modifier(_) => get_count(&screenshot, &cell, count_area),
+                        CellContent::Empty | CellContent::Unknown => 1,
+                    };
+                    (role, cell, content, count)
                 })
                 .collect::<Vec<_>>()
         );
-        if cache_images.len() > cache_images_count {
+        if cache.images.len() > cache_images_count {
             cache.modified = true;
         }
 
-        let modifier_ids_by_tags = modifier_ids.iter().into_group_map_by(|&&(tag, _, _)| tag);
         ProcessImageResult {
-            stash_area: CELL_GROUPS.get(&0).unwrap().area.translate(layout[&0]),
-            stash_modifier_ids: modifier_ids_by_tags
-                .get(&0u8)
-                .unwrap_or(&Vec::new())
+            stash_area: CELL_GROUPS
+                .iter()
+                .find(|entry| entry.role == CellGroupRole::Stash)
+                .map(|entry| entry.area.translate(layout[entry.key()]).scale(scale))
+                .unwrap(),
+            stash_cells: cell_contents
                 .iter()
-                .map(|&&(_, area, modifier_id)| (area, modifier_id))
+                .filter(|(role, _, _, _)| *role == CellGroupRole::Stash)
+                .map(|(_, cell, content, count)| {
+                    (
+                        cell.area.scale(scale),
+                        StashCell {
+                            content: *content,
+                            count: *count,
+                        },
+                    )
+                })
                 .collect(),
-            queue_modifier_ids: modifier_ids_by_tags
-                .get(&1u8)
-                .unwrap_or(&Vec::new())
+            queue_contents: cell_contents
                 .iter()
-                .map(|&&(_, area, modifier_id)| (area.x, modifier_id))
-                .sorted_by_key(|&(x, _)| x)
-                .map(|(_, modifier_id)| modifier_id)
+                .filter(|(role, _, _, _)| *role == CellGroupRole::Queue)
+                .sorted_by_key(|(_, cell, _, _)| cell.area.x)
+                .map(|&(_, _, content, _)| content)
+                .collect(),
+            layout: layout
+                .into_iter()
+                .map(|(tag, offset)| (tag, offset.scale(scale)))
                 .collect(),
         }
     })
@@ -0,0 +1,53 @@
+//! The modifiers in the stash, counted from the stash cells read from a capture.
+
+use std::collections::BTreeMap;
+
+use crate::image::CellContent;
+use crate::logic::ModifierId;
+
+/// A stash cell read from a capture.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StashCell {
+    pub content: CellContent,
+    /// Number of modifiers stacked in the cell, 1 unless a stack count was read.
+    pub count: usize,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct StashInventory {
+    cells: Vec<StashCell>,
+}
+
+impl StashInventory {
+    pub fn new(cells: impl IntoIterator<Item = StashCell>) -> Self {
+        Self {
+            cells: cells.into_iter().collect(),
+        }
+    }
+
+    /// Number of copies of each modifier, stack counts included.
+    pub fn get_modifier_counts(&self) -> BTreeMap<ModifierId, usize> {
+        self.cells.iter().fold(BTreeMap::new(), |mut counts, cell| {
+            if let Some(modifier_id) = cell.content.get_modifier_id() {
+                *counts.entry(modifier_id).or_default() += cell.count;
+            }
+
+            counts
+        })
+    }
+
+    /// Number of cells taken, whatever the size of their stacks.
+    pub fn get_taken_cell_count(&self) -> usize {
+        self.cells
+            .iter()
+            .filter(|cell| cell.content != CellContent::Empty)
+            .count()
+    }
+
+    pub fn get_unknown_cell_count(&self) -> usize {
+        self.cells
+            .iter()
+            .filter(|cell| cell.content == CellContent::Unknown)
+            .count()
+    }
+}
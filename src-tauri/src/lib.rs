@@ -0,0 +1,14 @@
+//! Image recognition and combo suggestion, shared by the app and the benchmarks.
+
+pub mod cache;
+pub mod catalog;
+pub mod data;
+pub mod image;
+pub mod inventory;
+pub mod logic;
+pub mod prices;
+pub mod rewards;
+pub mod utils;
+
+#[cfg(test)]
+mod tests;
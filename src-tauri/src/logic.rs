@@ -1,56 +1,50 @@
-use crate::utils::{DiscSynchronized, JsonDiscSynchronized};
-use crate::{collection, Cache};
+use crate::cache::Cache;
+use crate::collection;
+pub use crate::data::{Effect, Modifier, ModifierId, Reward};
+use crate::image::ManualLayout;
+use crate::rewards;
+use crate::utils::{stable_hash, DiscSynchronized, JsonDiscSynchronized, Rng};
 use itertools::Itertools;
 use log::{info, warn};
 use once_cell::sync::Lazy;
 use serde::{Deserialize, Serialize};
 use std::cmp::Ordering::Equal;
-use std::collections::hash_map::DefaultHasher;
+use std::cmp::Reverse;
 use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use std::error::Error;
 use std::fs::File;
-use std::hash::{Hash, Hasher};
 use std::io::BufReader;
+use std::sync::RwLock;
 use std::time::Instant;
+use thiserror::Error;
 use Effect::*;
 use Reward::*;
 
-const QUEUE_LENGTH: usize = 4;
+pub const QUEUE_LENGTH: usize = 4;
+
+const DATA: &str = include_str!("resources/data.json");
 
 const TIME_BUDGET_MS: u128 = 200;
 
-const REROLL_MULTIPLIER: f64 = 0.25;
-
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
-pub enum Reward {
-    Generic,
-    Armour,
-    Weapon,
-    Jewelry,
-    Gem,
-    Map,
-    DivinationCard,
-    Fragment,
-    Essence,
-    Harbinger,
-    Unique,
-    Delve,
-    Blight,
-    Ritual,
-    Currency,
-    Legion,
-    Breach,
-    Labyrinth,
-    Scarab,
-    Abyss,
-    Heist,
-    Expedition,
-    Delirium,
-    Metamorph,
-    Treant,
-}
-
-static REWARD_VALUES: Lazy<HashMap<Reward, u32>> = Lazy::new(|| {
+/// Maximum number of modifiers considered for the combos targeting rewards.
+const MAX_BOOSTING_MODIFIER_COUNT: usize = 12;
+
+/// Number of samples used to estimate the variance of the value of the combos targeting rewards.
+const MONTE_CARLO_SAMPLE_COUNT: usize = 256;
+
+/// Maximum number of combos targeting rewards whose value is adjusted for its variance, out of those with
+/// the highest expected value, which bounds the samples to `MONTE_CARLO_SAMPLE_COUNT` times as many.
+const MAX_RISK_ADJUSTED_COMBO_COUNT: usize = 16;
+
+pub const STASH_CAPACITY: usize = 64;
+
+/// Maximum number of surplus modifiers whose combinations are ranked when clearing the stash.
+const MAX_CLEARING_MODIFIER_COUNT: usize = 12;
+
+pub const DEFAULT_COMBO_WEIGHT: u32 = 100;
+
+/// Default value of each reward, used unless a price snapshot is imported.
+pub static DEFAULT_REWARD_VALUES: Lazy<HashMap<Reward, u32>> = Lazy::new(|| {
     collection![
         Generic => 1,
         Armour => 1,
@@ -80,26 +74,26 @@ static REWARD_VALUES: Lazy<HashMap<Reward, u32>> = Lazy::new(|| {
     ]
 });
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
-pub enum Effect {
-    Reroll { count: usize },
-    AdditionalReward,
-    DoubledReward,
-    Convert { to: Reward },
+static REWARD_VALUES: Lazy<RwLock<HashMap<Reward, f32>>> = Lazy::new(|| {
+    RwLock::new(
+        DEFAULT_REWARD_VALUES
+            .iter()
+            .map(|(&reward, &value)| (reward, value as f32))
+            .collect(),
+    )
+});
+
+pub fn get_rewards() -> Vec<Reward> {
+    DEFAULT_REWARD_VALUES.keys().copied().sorted().collect()
 }
 
-pub type ModifierId = u8;
+pub fn get_reward_value(reward: Reward) -> f32 {
+    REWARD_VALUES.read().unwrap()[&reward]
+}
 
-#[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
-pub struct Modifier {
-    pub id: ModifierId,
-    pub name: String,
-    pub recipe: BTreeSet<ModifierId>,
-    #[serde(skip_serializing)]
-    pub rewards: HashMap<Reward, usize>,
-    #[serde(skip_serializing)]
-    pub effect: Option<Effect>,
+/// Replaces the value of each reward, e.g. with imported prices.
+pub fn set_reward_values(reward_values: HashMap<Reward, f32>) {
+    *REWARD_VALUES.write().unwrap() = reward_values;
 }
 
 #[derive(PartialEq, Eq, Clone, Debug, Serialize, Deserialize)]
@@ -114,8 +108,7 @@ pub struct Modifiers {
 impl Modifiers {
     pub fn new() -> Self {
         let modifiers =
-            serde_json::from_str::<'_, Vec<Modifier>>(include_str!("resources/data.json"))
-                .expect("Invalid data.json!");
+            serde_json::from_str::<'_, Vec<Modifier>>(DATA).expect("Invalid data.json!");
         let by_id = modifiers
             .iter()
             .map(|modifier| (modifier.id, modifier.clone()))
@@ -156,21 +149,226 @@ impl Modifiers {
     }
 }
 
+impl Default for Modifiers {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub static MODIFIERS: Lazy<Modifiers> = Lazy::new(Modifiers::new);
 
+/// Fingerprint of the modifier data; any change invalidates the cached images and combos.
+pub fn get_data_fingerprint() -> u64 {
+    stable_hash(DATA)
+}
+
+/// Fingerprint of the parameters of the solver; any change invalidates the cached combos.
+pub fn get_solver_fingerprint() -> u64 {
+    stable_hash(&(
+        REWARD_VALUES
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(&reward, value)| (reward, value.to_bits()))
+            .sorted()
+            .collect_vec(),
+        rewards::MODEL_VERSION,
+        QUEUE_LENGTH,
+    ))
+}
+
 pub type ComboId = u64;
 
+/// Explains a suggested combo which is not a roster combo itself.
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub enum Hint {
+    /// The combo produces `modifier_id`, which is needed for the roster combo labeled `label`.
+    #[serde(rename_all = "camelCase")]
+    BuildsToward {
+        modifier_id: ModifierId,
+        label: String,
+    },
+    /// `count` copies of `modifier_id` are kept for the roster combos rather than used as fillers.
+    #[serde(rename_all = "camelCase")]
+    Reserved {
+        modifier_id: ModifierId,
+        count: usize,
+    },
+    /// The stash is full, and the combo frees `freed_cells` of its cells.
+    #[serde(rename_all = "camelCase")]
+    ClearingStash { freed_cells: usize },
+}
+
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Suggestion {
+    pub combo: Vec<ModifierId>,
+    pub hints: Vec<Hint>,
+}
+
+impl Suggestion {
+    pub fn new(combo: Vec<ModifierId>) -> Self {
+        Self {
+            combo,
+            hints: Vec::new(),
+        }
+    }
+}
+
 #[derive(Hash, PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct LabeledCombo {
     pub id: ComboId,
     pub label: String,
     pub combo: Vec<ModifierId>,
+    /// Relative priority, dividing the position of the combo in the roster; e.g. the second combo with
+    /// twice the default weight ranks as high as the first one.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    /// Number of runs after which the combo is no longer pursued, unlimited if `None`.
+    #[serde(default)]
+    pub max_runs: Option<u32>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    /// Maximum effective filler count of the custom combos building toward this one, defaults to
+    /// [`SolverSettings::max_effective_fillers`] if `None`.
+    #[serde(default)]
+    pub filler_tolerance: Option<usize>,
+}
+
+fn default_weight() -> u32 {
+    DEFAULT_COMBO_WEIGHT
+}
+
+fn default_enabled() -> bool {
+    true
 }
 
 impl LabeledCombo {
     pub fn new(id: u64, label: String, combo: Vec<ModifierId>) -> Self {
-        Self { id, label, combo }
+        Self {
+            id,
+            label,
+            combo,
+            weight: DEFAULT_COMBO_WEIGHT,
+            max_runs: None,
+            enabled: true,
+            filler_tolerance: None,
+        }
+    }
+
+    /// Modifiers needed to build the combo, including the combo itself, with their counts.
+    fn get_required_modifier_ids(&self) -> HashMap<ModifierId, usize> {
+        let mut required_modifier_ids = HashMap::<ModifierId, usize>::new();
+        for modifier_id in &self.combo {
+            *required_modifier_ids.entry(*modifier_id).or_default() += 1;
+            required_modifier_ids = MODIFIERS.components[modifier_id].iter().fold(
+                required_modifier_ids,
+                |mut required_modifier_ids, (&modifier_id, &modifier_id_count)| {
+                    *required_modifier_ids.entry(modifier_id).or_default() += modifier_id_count;
+                    required_modifier_ids
+                },
+            );
+        }
+
+        required_modifier_ids
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum SolverSettingsError {
+    #[error("the queue length must be between 1 and {}", QUEUE_LENGTH)]
+    InvalidQueueLength,
+    #[error("the stash threshold must be between 1 and {}", STASH_CAPACITY)]
+    InvalidStashThreshold,
+    #[error("the tier1 filler minimum count must be positive")]
+    InvalidTier1FillerMinCount,
+    #[error("the maximum effective filler count cannot exceed the queue length")]
+    InvalidMaxEffectiveFillers,
+    #[error("at least one reward must be targeted")]
+    NoTargetedRewards,
+}
+
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub enum SolverMode {
+    /// Progresses toward the roster combos.
+    Roster,
+    /// Maximizes the value of the given rewards, ignoring the roster.
+    TargetRewards(BTreeSet<Reward>),
+}
+
+impl Default for SolverMode {
+    fn default() -> Self {
+        Self::Roster
+    }
+}
+
+/// How the combos targeting rewards account for the luck of their drops.
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
+pub enum RiskPreference {
+    /// Ranks combos by their expected value.
+    Neutral,
+    /// Favors combos whose value varies the least (mean minus standard deviation).
+    Safe,
+    /// Favors combos whose value varies the most (mean plus standard deviation).
+    HighVariance,
+}
+
+impl Default for RiskPreference {
+    fn default() -> Self {
+        Self::Neutral
+    }
+}
+
+/// Tunes how the solver suggests custom combos.
+#[derive(Hash, PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SolverSettings {
+    /// Stash size from which tier1 modifiers used by the roster may act as fillers, and from which the
+    /// maximum effective filler count is no longer enforced.
+    pub stash_threshold: usize,
+    /// Minimum number of surplus copies for a tier1 modifier used by the roster to act as a filler.
+    pub tier1_filler_min_count: usize,
+    /// Maximum effective filler count of custom combos, unless overridden per combo.
+    pub max_effective_fillers: usize,
+    /// Number of modifiers the queue holds, and thus of custom combos; runs are counted once it is full.
+    pub queue_length: usize,
+    #[serde(default)]
+    pub mode: SolverMode,
+    /// Only applies when targeting rewards: the roster combos, recipes and clearing combos are ranked by their
+    /// priority, fillers or freed cells first, so the variance of their value hardly matters.
+    #[serde(default)]
+    pub risk_preference: RiskPreference,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        Self {
+            stash_threshold: 60,
+            tier1_filler_min_count: 3,
+            max_effective_fillers: 2,
+            queue_length: QUEUE_LENGTH,
+            mode: SolverMode::Roster,
+            risk_preference: RiskPreference::Neutral,
+        }
+    }
+}
+
+impl SolverSettings {
+    pub fn validate(&self) -> Result<(), SolverSettingsError> {
+        if !(1..=QUEUE_LENGTH).contains(&self.queue_length) {
+            Err(SolverSettingsError::InvalidQueueLength)
+        } else if !(1..=STASH_CAPACITY).contains(&self.stash_threshold) {
+            Err(SolverSettingsError::InvalidStashThreshold)
+        } else if self.tier1_filler_min_count == 0 {
+            Err(SolverSettingsError::InvalidTier1FillerMinCount)
+        } else if self.max_effective_fillers > self.queue_length {
+            Err(SolverSettingsError::InvalidMaxEffectiveFillers)
+        } else if self.mode == SolverMode::TargetRewards(BTreeSet::new()) {
+            Err(SolverSettingsError::NoTargetedRewards)
+        } else {
+            Ok(())
+        }
     }
 }
 
@@ -182,29 +380,51 @@ pub struct UserSettings {
     pub forbidden_modifier_ids: BTreeSet<ModifierId>,
     pub hotkey: String,
     pub show_tiers: bool,
+    #[serde(default)]
+    pub solver_settings: SolverSettings,
+    /// Used instead of the automatic layout detection when set.
+    #[serde(default)]
+    pub manual_layout: Option<ManualLayout>,
 }
 
 impl UserSettings {
-    pub fn get_filler_modifier_ids(&self) -> HashSet<ModifierId> {
-        let used_modifier_ids = self
-            .combo_roster
+    /// Enabled roster combos with their priority (lower is more important), most important first.
+    pub fn get_roster_combo_priorities(&self) -> impl Iterator<Item = (f32, &LabeledCombo)> {
+        self.combo_roster
             .iter()
-            .map(|&combo_id| {
-                &self
-                    .combo_catalog
+            .map(move |&combo_id| {
+                self.combo_catalog
                     .iter()
                     .find(|combo| combo.id == combo_id)
                     .unwrap()
-                    .combo
             })
-            .flat_map(|combo| {
-                combo.iter().flat_map(|modifier_id| {
-                    MODIFIERS.components[modifier_id]
-                        .keys()
-                        .chain(std::iter::once(modifier_id))
-                })
+            .enumerate()
+            .filter(|(_, combo)| combo.enabled)
+            .map(|(combo_index, combo)| {
+                (
+                    (combo_index as f32 + 1.0) * DEFAULT_COMBO_WEIGHT as f32 / combo.weight as f32,
+                    combo,
+                )
             })
-            .copied()
+            .sorted_by(|&(priority1, _), &(priority2, _)| {
+                priority1.partial_cmp(&priority2).unwrap_or(Equal)
+            })
+    }
+
+    pub fn get_filler_tolerance(&self, combo: &LabeledCombo) -> usize {
+        combo
+            .filler_tolerance
+            .unwrap_or(self.solver_settings.max_effective_fillers)
+    }
+
+    pub fn get_roster_combos(&self) -> impl Iterator<Item = &LabeledCombo> {
+        self.get_roster_combo_priorities().map(|(_, combo)| combo)
+    }
+
+    pub fn get_filler_modifier_ids(&self) -> HashSet<ModifierId> {
+        let used_modifier_ids = self
+            .get_roster_combos()
+            .flat_map(|combo| combo.get_required_modifier_ids().into_keys())
             .collect::<HashSet<_>>();
         MODIFIERS
             .by_id
@@ -231,6 +451,8 @@ impl DiscSynchronized for UserSettings {
             forbidden_modifier_ids: collection![54, 55, 56, 59, 61, 62],
             hotkey: "shift + x".to_owned(),
             show_tiers: false,
+            solver_settings: SolverSettings::default(),
+            manual_layout: None,
         }
     }
 
@@ -244,6 +466,12 @@ impl DiscSynchronized for UserSettings {
             .duplicates_by(|&combo| combo.id)
             .count()
             == 0
+            && self.combo_catalog.iter().all(|combo| combo.weight > 0)
+            && self.solver_settings.validate().is_ok()
+            && self
+                .manual_layout
+                .as_ref()
+                .map_or(true, ManualLayout::is_valid)
             && self.combo_roster.iter().duplicates().count() == 0
             && self.combo_roster.iter().all(|&combo_id| {
                 self.combo_catalog
@@ -260,6 +488,129 @@ impl DiscSynchronized for UserSettings {
 
 impl JsonDiscSynchronized for UserSettings {}
 
+/// How many times each combo has been run, kept apart from the user settings since it changes on
+/// activation rather than in the settings UI.
+#[derive(PartialEq, Eq, Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ComboRuns {
+    pub runs: BTreeMap<ComboId, u32>,
+    /// The roster combo which has been queued, or whose last modifier has been suggested, and which is counted
+    /// as run once the queue no longer holds it.
+    #[serde(default)]
+    pub pending_combo_id: Option<ComboId>,
+}
+
+impl ComboRuns {
+    pub fn get(&self, combo_id: ComboId) -> u32 {
+        self.runs.get(&combo_id).copied().unwrap_or_default()
+    }
+
+    pub fn is_exhausted(&self, combo: &LabeledCombo) -> bool {
+        combo
+            .max_runs
+            .filter(|&max_runs| self.get(combo.id) >= max_runs)
+            .is_some()
+    }
+
+    /// Counts a run of the pending combo once the queue is emptied or holds another combo being built, which
+    /// happens only after it has been run; returns the id of the combo. Activating again with the same queue
+    /// counts nothing.
+    pub fn observe_queue(
+        &mut self,
+        user_settings: &UserSettings,
+        queue: &[ModifierId],
+    ) -> Option<ComboId> {
+        let queue_length = user_settings.solver_settings.queue_length;
+        let pending_combo = self.pending_combo_id.and_then(|combo_id| {
+            user_settings
+                .combo_catalog
+                .iter()
+                .find(|combo| combo.id == combo_id)
+        });
+        let run_combo_id = match pending_combo {
+            Some(combo) if combo.combo.starts_with(queue) && queue.len() + 1 >= queue_length => {
+                return None;
+            }
+            // another modifier was queued instead of the last one
+            Some(_) if queue.len() == queue_length => None,
+            Some(combo) => {
+                *self.runs.entry(combo.id).or_default() += 1;
+                Some(combo.id)
+            }
+            None => None,
+        };
+
+        // the combo was queued without being suggested
+        self.pending_combo_id = user_settings
+            .get_roster_combos()
+            .find(|combo| combo.combo == queue)
+            .map(|combo| combo.id);
+        run_combo_id
+    }
+
+    /// Expects a run of the roster combo matching `suggestion`, if the suggested modifier completes it.
+    pub fn observe_suggestion(
+        &mut self,
+        user_settings: &UserSettings,
+        queue: &[ModifierId],
+        suggestion: &Suggestion,
+    ) {
+        if queue.len() + 1 == user_settings.solver_settings.queue_length {
+            if let Some(combo) = user_settings
+                .get_roster_combos()
+                .find(|combo| combo.combo == suggestion.combo)
+            {
+                self.pending_combo_id = Some(combo.id);
+            }
+        }
+    }
+
+    pub fn reset(&mut self, combo_id: ComboId) {
+        self.runs.remove(&combo_id);
+        if self.pending_combo_id == Some(combo_id) {
+            self.pending_combo_id = None;
+        }
+    }
+
+    /// The user settings without the roster combos which have reached their maximum run count.
+    pub fn get_pending_user_settings(&self, user_settings: &UserSettings) -> UserSettings {
+        UserSettings {
+            combo_roster: user_settings
+                .combo_roster
+                .iter()
+                .filter(|&&combo_id| {
+                    user_settings
+                        .combo_catalog
+                        .iter()
+                        .find(|combo| combo.id == combo_id)
+                        .filter(|combo| self.is_exhausted(combo))
+                        .is_none()
+                })
+                .copied()
+                .collect(),
+            ..user_settings.clone()
+        }
+    }
+}
+
+impl DiscSynchronized for ComboRuns {
+    const FILE_NAME: &'static str = "archbroski\\runs.json";
+
+    fn create_new() -> Self {
+        Self::default()
+    }
+
+    fn save_impl(&self, writer: &mut std::io::BufWriter<File>) -> Result<(), Box<dyn Error>> {
+        <Self as JsonDiscSynchronized>::save_impl(self, writer)
+    }
+
+    fn load_impl(reader: BufReader<File>) -> Result<Self, Box<dyn Error>> {
+        <Self as JsonDiscSynchronized>::load_impl(reader)
+    }
+}
+
+impl JsonDiscSynchronized for ComboRuns {}
+
 fn owned_modifier_count(stash: &BTreeMap<ModifierId, usize>, modifier_id: ModifierId) -> usize {
     stash.get(&modifier_id).copied().unwrap_or_default()
 }
@@ -268,66 +619,175 @@ fn owns_modifier(stash: &BTreeMap<ModifierId, usize>, modifier_id: ModifierId) -
     owned_modifier_count(stash, modifier_id) > 0
 }
 
-fn get_combo_value(combo: &[ModifierId]) -> f32 {
-    (0..combo.len()).fold(0f32, |value, index| {
-        let modifiers = (0..index + 1)
-            .map(|index| &MODIFIERS.by_id[&combo[index]])
-            .collect_vec();
-        let effects = modifiers
-            .iter()
-            .flat_map(|&modifier| modifier.effect.iter())
-            .collect_vec();
-        let additional_reward_count: usize = effects
-            .iter()
-            .filter(|&&&effect| effect == AdditionalReward)
-            .count();
-        let doubled_rewards = effects.iter().any(|&&effect| effect == DoubledReward);
-        let reroll_count = effects
-            .iter()
-            .map(|&&effect| {
-                if let Reroll { count } = effect {
-                    count
-                } else {
-                    0
-                }
-            })
-            .sum::<usize>();
+/// The stash left after taking out the given modifiers, one copy each, or `None` if it does not hold enough
+/// copies of them.
+fn consume<'a>(
+    stash: &BTreeMap<ModifierId, usize>,
+    modifier_ids: impl IntoIterator<Item = &'a ModifierId>,
+) -> Option<BTreeMap<ModifierId, usize>> {
+    modifier_ids
+        .into_iter()
+        .try_fold(stash.clone(), |mut stash, &modifier_id| {
+            match owned_modifier_count(&stash, modifier_id) {
+                0 => return None,
+                1 => stash.remove(&modifier_id),
+                count => stash.insert(modifier_id, count - 1),
+            };
+            Some(stash)
+        })
+}
 
-        let rewards = modifiers.iter().map(|&modifier| &modifier.rewards).fold(
-            HashMap::new(),
-            |mut rewards, rewards_| {
-                rewards.extend(rewards_);
-                rewards
-            },
-        );
-        let rewards = if let Some(&converted_reward) =
-            effects.iter().fold(None, |converted_reward, &effect| {
-                if let Convert { to } = effect {
-                    Some(to)
-                } else {
-                    converted_reward
-                }
-            }) {
-            collection! {converted_reward => rewards.values().sum()}
-        } else {
-            rewards
-        };
+/// Whether the stash holds enough copies of the given modifiers to use all of them at once.
+fn can_afford<'a>(
+    stash: &BTreeMap<ModifierId, usize>,
+    modifier_ids: impl IntoIterator<Item = &'a ModifierId>,
+) -> bool {
+    consume(stash, modifier_ids).is_some()
+}
 
-        value
-            + rewards
-                .into_iter()
-                .map(|(reward, reward_count)| {
-                    let base_reward_value = REWARD_VALUES[&reward];
-                    base_reward_value as f32
-                        * (reward_count + additional_reward_count) as f32
-                        * (if doubled_rewards { 2 } else { 1 }) as f32
-                        * (1.0 + reroll_count as f64 * REROLL_MULTIPLIER) as f32
-                })
-                .sum::<f32>()
+/// Takes a copy of the modifier out of the stash, building it from its recipe if it is not owned. The
+/// recipes whose ingredients are all owned, and therefore can be queued right away, are collected into
+/// `ready_recipes`.
+fn reserve(
+    stash: BTreeMap<ModifierId, usize>,
+    modifier_id: ModifierId,
+    ready_recipes: &mut Vec<(ModifierId, BTreeSet<ModifierId>)>,
+) -> Option<BTreeMap<ModifierId, usize>> {
+    if let Some(stash) = consume(&stash, &[modifier_id]) {
+        return Some(stash);
+    }
+
+    let recipe = &MODIFIERS.by_id[&modifier_id].recipe;
+    if recipe.is_empty() {
+        return None;
+    }
+
+    if let Some(stash) = consume(&stash, recipe) {
+        ready_recipes.push((modifier_id, recipe.clone()));
+        return Some(stash);
+    }
+
+    recipe.iter().try_fold(stash, |stash, &modifier_id| {
+        reserve(stash, modifier_id, ready_recipes)
     })
 }
 
-fn get_produced_modifier_ids(combo: &[ModifierId]) -> HashMap<BTreeSet<ModifierId>, ModifierId> {
+/// Like [`reserve`], but takes whatever is owned rather than failing on missing modifiers.
+fn reserve_owned(
+    stash: &mut BTreeMap<ModifierId, usize>,
+    modifier_id: ModifierId,
+    reserved_modifier_ids: &mut BTreeMap<ModifierId, usize>,
+) {
+    match stash.get_mut(&modifier_id).filter(|count| **count > 0) {
+        Some(count) => {
+            *count -= 1;
+            *reserved_modifier_ids.entry(modifier_id).or_default() += 1;
+        }
+        None => {
+            for &modifier_id in &MODIFIERS.by_id[&modifier_id].recipe {
+                reserve_owned(stash, modifier_id, reserved_modifier_ids);
+            }
+        }
+    }
+}
+
+/// How many copies of each modifier in the stash are needed by the roster combos, more important combos
+/// reserving first. Modifiers which are not owned reserve the ingredients of their recipe instead.
+pub fn get_reserved_modifier_ids(
+    user_settings: &UserSettings,
+    stash: &BTreeMap<ModifierId, usize>,
+) -> BTreeMap<ModifierId, usize> {
+    let mut stash = stash.clone();
+    let mut reserved_modifier_ids = BTreeMap::new();
+    for combo in user_settings.get_roster_combos() {
+        for &modifier_id in &combo.combo {
+            reserve_owned(&mut stash, modifier_id, &mut reserved_modifier_ids);
+        }
+    }
+
+    reserved_modifier_ids
+}
+
+/// Copies of the modifier which the roster combos do not need.
+fn surplus_modifier_count(
+    stash: &BTreeMap<ModifierId, usize>,
+    reserved_modifier_ids: &BTreeMap<ModifierId, usize>,
+    modifier_id: ModifierId,
+) -> usize {
+    owned_modifier_count(stash, modifier_id).saturating_sub(
+        reserved_modifier_ids
+            .get(&modifier_id)
+            .copied()
+            .unwrap_or_default(),
+    )
+}
+
+/// Explains which modifiers would have been used as tier1 fillers, if the roster combos did not need them.
+fn get_reservation_hints(
+    user_settings: &UserSettings,
+    stash: &BTreeMap<ModifierId, usize>,
+    combo: &[ModifierId],
+) -> Vec<Hint> {
+    get_reserved_modifier_ids(user_settings, stash)
+        .into_iter()
+        .filter(|&(modifier_id, _)| {
+            !combo.contains(&modifier_id)
+                && owned_modifier_count(stash, modifier_id)
+                    >= user_settings.solver_settings.tier1_filler_min_count
+        })
+        .map(|(modifier_id, count)| Hint::Reserved { modifier_id, count })
+        .collect()
+}
+
+pub fn get_combo_value(combo: &[ModifierId]) -> f32 {
+    get_filtered_combo_value(combo, |_| true)
+}
+
+/// Same as [`get_combo_value`], but only counting the given rewards.
+pub fn get_combo_reward_value(combo: &[ModifierId], rewards: &BTreeSet<Reward>) -> f32 {
+    get_filtered_combo_value(combo, |reward| rewards.contains(reward))
+}
+
+fn get_filtered_combo_value(combo: &[ModifierId], is_counted: impl Fn(&Reward) -> bool) -> f32 {
+    rewards::get_expected_values(combo)
+        .into_iter()
+        .filter(|(reward, _)| is_counted(reward))
+        .map(|(_, value)| value)
+        .sum()
+}
+
+/// Adjusts the expected value of the given rewards of a combo for their variance. The variance is estimated
+/// with a seed derived from the combo, so that the same combo always gets the same value.
+fn get_risk_adjusted_value(
+    combo: &[ModifierId],
+    rewards: &BTreeSet<Reward>,
+    value: f32,
+    risk_preference: RiskPreference,
+) -> f32 {
+    let estimate_combo_value = || {
+        rewards::estimate_combo_value(
+            combo,
+            |reward| rewards.contains(reward),
+            MONTE_CARLO_SAMPLE_COUNT,
+            &mut Rng::new(stable_hash(combo)),
+        )
+    };
+    match risk_preference {
+        RiskPreference::Neutral => value,
+        RiskPreference::Safe => {
+            let estimate = estimate_combo_value();
+            estimate.mean - estimate.get_std_dev()
+        }
+        RiskPreference::HighVariance => {
+            let estimate = estimate_combo_value();
+            estimate.mean + estimate.get_std_dev()
+        }
+    }
+}
+
+pub fn get_produced_modifier_ids(
+    combo: &[ModifierId],
+) -> HashMap<BTreeSet<ModifierId>, ModifierId> {
     struct State {
         used_modifiers_ids: HashSet<ModifierId>,
         produced_modifier_ids: HashMap<BTreeSet<ModifierId>, ModifierId>,
@@ -336,6 +796,8 @@ fn get_produced_modifier_ids(combo: &[ModifierId]) -> HashMap<BTreeSet<ModifierI
     combo
         .iter()
         .powerset()
+        // the base modifiers have an empty recipe, but are never produced
+        .filter(|modifier_ids| !modifier_ids.is_empty())
         .fold(
             State {
                 used_modifiers_ids: HashSet::new(),
@@ -365,6 +827,30 @@ fn get_produced_modifier_ids(combo: &[ModifierId]) -> HashMap<BTreeSet<ModifierI
         .produced_modifier_ids
 }
 
+/// The number of modifiers in the combo which do not advance towards any of the roster combos: those
+/// not used in any recipe, and those used in recipes producing fillers.
+pub fn get_effective_filler_count(
+    combo: &[ModifierId],
+    filler_modifier_ids: &HashSet<ModifierId>,
+) -> usize {
+    let produced_modifier_ids = get_produced_modifier_ids(combo);
+    combo.len()
+        - produced_modifier_ids
+            .keys()
+            .map(|recipe| recipe.len())
+            .sum::<usize>()
+        + produced_modifier_ids
+            .iter()
+            .map(|(recipe, &modifier_id)| {
+                if filler_modifier_ids.contains(&modifier_id) {
+                    recipe.len()
+                } else {
+                    0
+                }
+            })
+            .sum::<usize>()
+}
+
 fn get_unordered_combo_value(
     queue: &[ModifierId],
     unordered_combo: &BTreeSet<ModifierId>,
@@ -400,66 +886,347 @@ fn suggest_active_combo(
     queue: &[ModifierId],
 ) -> Option<Vec<ModifierId>> {
     user_settings
-        .combo_roster
-        .iter()
-        .map(|&combo_id| {
-            &user_settings
-                .combo_catalog
+        .get_roster_combos()
+        .map(|combo| &combo.combo)
+        .find(|combo| combo.starts_with(queue) && can_afford(stash, &combo[queue.len()..]))
+        .cloned()
+        .map(|combo| {
+            info!("suggested active combo: {:?}", combo);
+            combo
+        })
+}
+
+/// Suggests a combo which builds the missing intermediates of the first roster combo that is not owned
+/// yet, but can be built from the stash (and the queue). The rest of the combo is filled with modifiers
+/// which none of the roster combos need.
+fn suggest_recipe_combo(
+    user_settings: &UserSettings,
+    stash: &BTreeMap<ModifierId, usize>,
+    queue: &[ModifierId],
+) -> Option<Suggestion> {
+    let queue_length = user_settings.solver_settings.queue_length;
+    let filler_modifier_ids = user_settings.get_filler_modifier_ids();
+    let reserved_modifier_ids = get_reserved_modifier_ids(user_settings, stash);
+    let available_modifier_ids = queue.iter().fold(stash.clone(), |mut stash, &modifier_id| {
+        *stash.entry(modifier_id).or_default() += 1;
+        stash
+    });
+    user_settings.get_roster_combos().find_map(|roster_combo| {
+        let mut ready_recipes = Vec::new();
+        let surplus_modifier_ids = roster_combo
+            .combo
+            .iter()
+            .try_fold(available_modifier_ids.clone(), |stash, &modifier_id| {
+                reserve(stash, modifier_id, &mut ready_recipes)
+            })?;
+
+        let (mut combo, produced_modifier_ids) = ready_recipes.into_iter().fold(
+            (BTreeSet::new(), HashSet::new()),
+            |(mut combo, mut produced_modifier_ids), (modifier_id, recipe)| {
+                if combo.len() + recipe.len() <= queue_length && combo.is_disjoint(&recipe) {
+                    combo.extend(recipe);
+                    produced_modifier_ids.insert(modifier_id);
+                }
+
+                (combo, produced_modifier_ids)
+            },
+        );
+        if produced_modifier_ids.is_empty()
+            || !queue.iter().all_unique()
+            || !queue.iter().all(|modifier_id| combo.contains(modifier_id))
+        {
+            return None;
+        }
+
+        let produces_all = |combo: &BTreeSet<ModifierId>| {
+            get_produced_modifier_ids(&combo.iter().copied().collect_vec())
+                .values()
+                .copied()
+                .collect::<HashSet<_>>()
+                .is_superset(&produced_modifier_ids)
+        };
+        for modifier_id in surplus_modifier_ids
+            .iter()
+            .filter(|&(modifier_id, _)| {
+                !combo.contains(modifier_id)
+                    && !user_settings.forbidden_modifier_ids.contains(modifier_id)
+                    && (filler_modifier_ids.contains(modifier_id)
+                        || surplus_modifier_count(stash, &reserved_modifier_ids, *modifier_id) > 0)
+            })
+            .sorted_by_key(|&(modifier_id, &count)| {
+                (!filler_modifier_ids.contains(modifier_id), Reverse(count))
+            })
+            .map(|(&modifier_id, _)| modifier_id)
+        {
+            if combo.len() == queue_length {
+                break;
+            }
+
+            combo.insert(modifier_id);
+            if !produces_all(&combo) {
+                combo.remove(&modifier_id);
+            }
+        }
+
+        if combo.len() < queue_length
+            || get_effective_filler_count(
+                &combo.iter().copied().collect_vec(),
+                &filler_modifier_ids,
+            ) > user_settings.get_filler_tolerance(roster_combo)
+        {
+            return None;
+        }
+
+        get_unordered_combo_value(queue, &combo, &produced_modifier_ids).map(|(combo, _)| {
+            info!(
+                "suggested recipe combo: {:?}, building toward: {:?}",
+                combo, roster_combo.label
+            );
+            Suggestion {
+                combo,
+                hints: produced_modifier_ids
+                    .iter()
+                    .sorted()
+                    .map(|&modifier_id| Hint::BuildsToward {
+                        modifier_id,
+                        label: roster_combo.label.clone(),
+                    })
+                    .collect(),
+            }
+        })
+    })
+}
+
+/// Suggests the combo which frees the most stash cells once the stash is full, out of the combinations of the
+/// most common copies which the roster combos do not need; ties go to the most valuable one. The modifiers it
+/// produces, possibly from queued ones, return to the stash.
+fn suggest_clearing_combo(
+    user_settings: &UserSettings,
+    stash: &BTreeMap<ModifierId, usize>,
+    stash_cell_count: usize,
+    queue: &[ModifierId],
+) -> Option<Suggestion> {
+    let queue_length = user_settings.solver_settings.queue_length;
+    if stash_cell_count < STASH_CAPACITY
+        || queue.len() >= queue_length
+        || !queue.iter().all_unique()
+    {
+        return None;
+    }
+
+    let reserved_modifier_ids = get_reserved_modifier_ids(user_settings, stash);
+    let is_stacked = stash_cell_count < stash.values().sum();
+    stash
+        .keys()
+        .copied()
+        .filter(|modifier_id| {
+            !queue.contains(modifier_id)
+                && !user_settings.forbidden_modifier_ids.contains(modifier_id)
+                && surplus_modifier_count(stash, &reserved_modifier_ids, *modifier_id) > 0
+        })
+        .sorted_by_key(|&modifier_id| {
+            (
+                Reverse(!is_stacked || owned_modifier_count(stash, modifier_id) == 1),
+                Reverse(surplus_modifier_count(
+                    stash,
+                    &reserved_modifier_ids,
+                    modifier_id,
+                )),
+                modifier_id,
+            )
+        })
+        .take(MAX_CLEARING_MODIFIER_COUNT)
+        .combinations(queue_length - queue.len())
+        .filter_map(|combo_suffix| {
+            let combo = queue
                 .iter()
-                .find(|combo| combo.id == combo_id)
-                .unwrap()
-                .combo
+                .copied()
+                .chain(combo_suffix)
+                .collect::<BTreeSet<_>>();
+            let produced_modifier_ids =
+                get_produced_modifier_ids(&combo.iter().copied().collect_vec())
+                    .into_values()
+                    .collect::<HashSet<_>>();
+            let freed_cells =
+                get_freed_cell_count(stash, is_stacked, queue, &combo, &produced_modifier_ids);
+            if freed_cells > 0 {
+                get_unordered_combo_value(queue, &combo, &produced_modifier_ids)
+                    .map(|(combo, value)| (combo, freed_cells as usize, value))
+            } else {
+                None
+            }
+        })
+        .max_by_key(|&(_, freed_cells, value)| (freed_cells, value.floor() as i32))
+        .map(|(combo, freed_cells, _)| {
+            info!("suggested clearing combo: {:?}", combo);
+            Suggestion {
+                hints: vec![Hint::ClearingStash { freed_cells }],
+                combo,
+            }
+        })
+}
+
+/// Net number of stash cells freed by completing the queue into `combo`: the cells of the modifiers taken out
+/// of the stash, minus those of the modifiers it produces, which return to the stash. With stacks, taking a
+/// modifier frees its cell only if it was the last one, and a produced modifier only takes a cell if none is
+/// left.
+fn get_freed_cell_count(
+    stash: &BTreeMap<ModifierId, usize>,
+    is_stacked: bool,
+    queue: &[ModifierId],
+    combo: &BTreeSet<ModifierId>,
+    produced_modifier_ids: &HashSet<ModifierId>,
+) -> isize {
+    let taken_modifier_ids = combo
+        .iter()
+        .copied()
+        .filter(|modifier_id| !queue.contains(modifier_id))
+        .collect::<HashSet<_>>();
+    let freed_cell_count = taken_modifier_ids
+        .iter()
+        .filter(|&&modifier_id| !is_stacked || owned_modifier_count(stash, modifier_id) == 1)
+        .count();
+    let taken_cell_count = produced_modifier_ids
+        .iter()
+        .filter(|&&modifier_id| {
+            !is_stacked
+                || owned_modifier_count(stash, modifier_id)
+                    == taken_modifier_ids.contains(&modifier_id) as usize
+        })
+        .count();
+    freed_cell_count as isize - taken_cell_count as isize
+}
+
+/// Suggests the combo with the highest value of the targeted rewards, out of the owned modifiers which
+/// either have such rewards or effects boosting them. Any remaining slots are filled with the most
+/// common modifiers.
+fn suggest_reward_combo(
+    user_settings: &UserSettings,
+    stash: &BTreeMap<ModifierId, usize>,
+    queue: &[ModifierId],
+    rewards: &BTreeSet<Reward>,
+) -> Option<Vec<ModifierId>> {
+    let combo_suffix_length = user_settings
+        .solver_settings
+        .queue_length
+        .checked_sub(queue.len())
+        .filter(|&combo_suffix_length| combo_suffix_length > 0)?;
+    let (boosting_modifier_ids, filler_modifier_ids): (Vec<_>, Vec<_>) = stash
+        .iter()
+        .filter(|&(modifier_id, &count)| {
+            count > 0
+                && !queue.contains(modifier_id)
+                && !user_settings.forbidden_modifier_ids.contains(modifier_id)
+        })
+        .map(|(modifier_id, _)| &MODIFIERS.by_id[modifier_id])
+        .filter(|modifier| match modifier.effect {
+            Some(Convert { to }) => rewards.contains(&to),
+            _ => true,
         })
-        .find(|combo| {
-            (0..queue.len()).all(|index| queue[index] == combo[index])
-                && combo
+        .partition(|modifier| {
+            modifier
+                .rewards
+                .keys()
+                .any(|reward| rewards.contains(reward))
+                || modifier.effect.is_some()
+        });
+    let boosting_modifier_ids = boosting_modifier_ids
+        .into_iter()
+        .map(|modifier| (modifier.id, get_combo_reward_value(&[modifier.id], rewards)))
+        .sorted_by(|&(_, value1), &(_, value2)| value2.partial_cmp(&value1).unwrap_or(Equal))
+        .map(|(modifier_id, _)| modifier_id)
+        .take(MAX_BOOSTING_MODIFIER_COUNT)
+        .collect_vec();
+    let filler_modifier_ids = filler_modifier_ids
+        .into_iter()
+        .map(|modifier| modifier.id)
+        .sorted_by_key(|&modifier_id| Reverse(owned_modifier_count(stash, modifier_id)))
+        .collect_vec();
+
+    boosting_modifier_ids
+        .iter()
+        .copied()
+        .combinations(combo_suffix_length.min(boosting_modifier_ids.len()))
+        .filter_map(|mut combo_suffix| {
+            combo_suffix.extend(
+                filler_modifier_ids
                     .iter()
-                    .skip(queue.len())
-                    .all(|&modifier_id| owns_modifier(stash, modifier_id))
+                    .take(combo_suffix_length - combo_suffix.len()),
+            );
+            if combo_suffix.len() < combo_suffix_length {
+                return None;
+            }
+
+            combo_suffix
+                .into_iter()
+                .permutations(combo_suffix_length)
+                .map(|combo_suffix| queue.iter().copied().chain(combo_suffix).collect_vec())
+                .map(|combo| {
+                    let value = get_combo_reward_value(&combo, rewards);
+                    (combo, value)
+                })
+                .max_by(|(_, value1), (_, value2)| value1.partial_cmp(value2).unwrap_or(Equal))
         })
-        .cloned()
-        .map(|combo| {
-            info!("suggested active combo: {:?}", combo);
+        .filter(|&(_, value)| value > 0.0)
+        .sorted_by(|(_, value1), (_, value2)| value2.partial_cmp(value1).unwrap_or(Equal))
+        .take(MAX_RISK_ADJUSTED_COMBO_COUNT)
+        .map(|(combo, value)| {
+            let value = get_risk_adjusted_value(
+                &combo,
+                rewards,
+                value,
+                user_settings.solver_settings.risk_preference,
+            );
+            (combo, value)
+        })
+        .max_by(|(_, value1), (_, value2)| value1.partial_cmp(value2).unwrap_or(Equal))
+        .map(|(combo, value)| {
+            info!(
+                "suggested reward combo: {:?}, value: {:?}, rewards: {:?}",
+                combo, value, rewards
+            );
             combo
         })
 }
 
 /// iteration 0: all recipes which advance towards one of the combos
 /// iteration 1: + fillers
-/// iteration 2: + tier1 mods as fillers (>= 3) (only if stash is >= 60)
-/// iteration 3: allow effective filler count == 4 (only if stash is >= 60)
-fn suggest_custom_combo(
+/// iteration 2: + tier1 mods as fillers (>= 3 not reserved by the roster) (only if >= 60 stash cells taken)
+/// iteration 3: allow effective filler count == 4 (only if >= 60 stash cells taken)
+///
+/// The thresholds above are the defaults of [`SolverSettings`]. When targeting rewards, the roster is
+/// ignored and the combo with the highest value of these rewards is suggested instead.
+pub fn suggest_custom_combo(
     user_settings: &UserSettings,
     stash: &BTreeMap<ModifierId, usize>,
+    stash_cell_count: usize,
     queue: &[ModifierId],
 ) -> Option<Vec<ModifierId>> {
+    if let SolverMode::TargetRewards(rewards) = &user_settings.solver_settings.mode {
+        return suggest_reward_combo(user_settings, stash, queue, rewards);
+    }
+
+    let solver_settings = &user_settings.solver_settings;
     let filler_modifiers_ids = user_settings.get_filler_modifier_ids();
+    let reserved_modifier_ids = get_reserved_modifier_ids(user_settings, stash);
+    let filler_tolerances = user_settings.get_roster_combos().fold(
+        HashMap::<ModifierId, usize>::new(),
+        |mut filler_tolerances, combo| {
+            for modifier_id in combo.get_required_modifier_ids().into_keys() {
+                let filler_tolerance = filler_tolerances.entry(modifier_id).or_default();
+                *filler_tolerance =
+                    (*filler_tolerance).max(user_settings.get_filler_tolerance(combo));
+            }
+
+            filler_tolerances
+        },
+    );
 
     let mut usable_modifier_ids = user_settings
-        .combo_roster
-        .iter()
-        .map(|&combo_id| {
-            &user_settings
-                .combo_catalog
-                .iter()
-                .find(|combo| combo.id == combo_id)
-                .unwrap()
-                .combo
-        })
-        .enumerate()
-        .map(|(combo_index, combo)| ((combo_index as f32 + 1.0), combo))
+        .get_roster_combo_priorities()
         .flat_map(|(combo_priority, combo)| {
-            let mut required_modifier_ids = HashMap::new();
-            for modifier_id in combo {
-                required_modifier_ids.insert(*modifier_id, 1);
-                required_modifier_ids = MODIFIERS.components[modifier_id].iter().fold(
-                    required_modifier_ids,
-                    |mut required_modifier_ids, (&modifier_id, &modifier_id_count)| {
-                        *required_modifier_ids.entry(modifier_id).or_default() += modifier_id_count;
-                        required_modifier_ids
-                    },
-                );
-            }
+            let required_modifier_ids = combo.get_required_modifier_ids();
+            let combo = &combo.combo;
 
             let mut owned_modifier_ids = HashMap::new();
             let mut modifier_ids = combo
@@ -485,7 +1252,7 @@ fn suggest_custom_combo(
                 .map(|(&modifier_id, &required_count)| {
                     (
                         modifier_id,
-                        combo_priority as f32,
+                        combo_priority,
                         (owned_modifier_ids
                             .get(&modifier_id)
                             .copied()
@@ -503,10 +1270,7 @@ fn suggest_custom_combo(
                         .difference(&queue.iter().copied().collect())
                         .copied()
                         .collect::<BTreeSet<_>>();
-                    !remanining_recipe.is_empty()
-                        && remanining_recipe
-                            .iter()
-                            .all(|&modifier_id| owns_modifier(stash, modifier_id))
+                    !remanining_recipe.is_empty() && can_afford(stash, &remanining_recipe)
                 })
                 .collect_vec()
         })
@@ -545,9 +1309,15 @@ fn suggest_custom_combo(
         MODIFIERS
             .by_id
             .iter()
-            .filter(|&(&modifier_id, _)| !filler_modifiers_ids.contains(&modifier_id))
+            .filter(|&(&modifier_id, _)| {
+                !filler_modifiers_ids.contains(&modifier_id)
+                    && !user_settings.forbidden_modifier_ids.contains(&modifier_id)
+            })
             .filter_map(|(&modifier_id, modifier)| {
-                if modifier.recipe.is_empty() && owned_modifier_count(stash, modifier_id) > 2 {
+                if modifier.recipe.is_empty()
+                    && surplus_modifier_count(stash, &reserved_modifier_ids, modifier_id)
+                        >= solver_settings.tier1_filler_min_count
+                {
                     Some(modifier_id)
                 } else {
                     None
@@ -555,8 +1325,8 @@ fn suggest_custom_combo(
             })
             .sorted_by(|&modifier_id1, &modifier_id2| {
                 Ord::cmp(
-                    &owned_modifier_count(stash, modifier_id1),
-                    &owned_modifier_count(stash, modifier_id2),
+                    &surplus_modifier_count(stash, &reserved_modifier_ids, modifier_id1),
+                    &surplus_modifier_count(stash, &reserved_modifier_ids, modifier_id2),
                 )
                 .reverse()
             })
@@ -576,13 +1346,7 @@ fn suggest_custom_combo(
             return None;
         }
 
-        if iteration == 2
-            && stash
-                .iter()
-                .map(|(_, &modifier_count)| modifier_count)
-                .sum::<usize>()
-                < 60
-        {
+        if iteration == 2 && stash_cell_count < solver_settings.stash_threshold {
             return None;
         }
 
@@ -633,7 +1397,7 @@ fn suggest_custom_combo(
                         }
 
                         combo.extend(queue);
-                        if combo.len() > QUEUE_LENGTH {
+                        if combo.len() > solver_settings.queue_length {
                             return None;
                         }
 
@@ -641,28 +1405,22 @@ fn suggest_custom_combo(
                             .map(|(combo, value)| (index, combo, 1.0 / value))
                     })
                 {
-                    if combo.len() == QUEUE_LENGTH {
-                        let produced_modifier_ids = get_produced_modifier_ids(&combo);
-                        let combo_effective_filler_count = 4 - produced_modifier_ids
-                            .keys()
-                            .map(|recipe| recipe.len())
-                            .sum::<usize>()
-                            + produced_modifier_ids
-                                .iter()
-                                .map(|(recipe, &modifier_id)| {
-                                    if filler_modifiers_ids.contains(&modifier_id) {
-                                        recipe.len()
-                                    } else {
-                                        0
-                                    }
-                                })
-                                .sum::<usize>();
+                    if combo.len() == solver_settings.queue_length {
+                        let combo_effective_filler_count =
+                            get_effective_filler_count(&combo, &filler_modifiers_ids);
                         if combo_effective_filler_count == 0 {
                             suggested_combo = Some((combo, (combo_effective_filler_count, value)));
                             break;
                         }
 
-                        if (combo_effective_filler_count <= 2 || iteration == 3)
+                        // the most tolerant of the roster combos the combo builds toward
+                        let filler_tolerance = get_produced_modifier_ids(&combo)
+                            .values()
+                            .filter_map(|modifier_id| filler_tolerances.get(modifier_id))
+                            .max()
+                            .copied()
+                            .unwrap_or(solver_settings.max_effective_fillers);
+                        if (combo_effective_filler_count <= filler_tolerance || iteration == 3)
                             && suggested_combo
                                 .as_ref()
                                 .filter(|&(_, score)| {
@@ -691,60 +1449,119 @@ fn suggest_custom_combo(
     }
 }
 
+/// `stash_cell_count` is the number of taken stash cells, which hold stacks of any number of modifiers.
 pub fn suggest_combo(
     user_settings: &UserSettings,
     stash: &BTreeMap<ModifierId, usize>,
+    stash_cell_count: usize,
     queue: &[ModifierId],
-) -> Option<Vec<ModifierId>> {
-    if queue.len() == 4 {
-        warn!("cannot suggest a combo with 4 queued modifiers");
+) -> Option<Suggestion> {
+    if queue.len() >= user_settings.solver_settings.queue_length {
+        warn!("cannot suggest a combo with a full queue");
         None
     } else {
-        suggest_active_combo(user_settings, stash, queue)
-            .or_else(|| suggest_custom_combo(user_settings, stash, queue))
-            .or_else(|| {
-                warn!("failed to suggest a combo");
-                None
-            })
+        match user_settings.solver_settings.mode {
+            SolverMode::Roster => suggest_active_combo(user_settings, stash, queue)
+                .map(Suggestion::new)
+                .or_else(|| suggest_clearing_combo(user_settings, stash, stash_cell_count, queue))
+                .or_else(|| suggest_recipe_combo(user_settings, stash, queue))
+                .or_else(|| {
+                    suggest_custom_combo(user_settings, stash, stash_cell_count, queue).map(
+                        |combo| Suggestion {
+                            hints: get_reservation_hints(user_settings, stash, &combo),
+                            combo,
+                        },
+                    )
+                }),
+            SolverMode::TargetRewards(_) => {
+                suggest_custom_combo(user_settings, stash, stash_cell_count, queue)
+                    .map(Suggestion::new)
+            }
+        }
+        .or_else(|| {
+            warn!("failed to suggest a combo");
+            None
+        })
     }
 }
 
+/// Stable hash of the settings the solver depends on: the enabled combos of the roster (in order, with their
+/// labels since hints refer to them, and their constraints, but not their ids), the forbidden modifiers and
+/// the solver settings.
+pub fn get_settings_cache_key(user_settings: &UserSettings) -> u64 {
+    stable_hash(&(
+        user_settings
+            .get_roster_combos()
+            .map(|combo| {
+                (
+                    &combo.label,
+                    &combo.combo,
+                    combo.weight,
+                    user_settings.get_filler_tolerance(combo),
+                )
+            })
+            .collect_vec(),
+        &user_settings.forbidden_modifier_ids,
+        &user_settings.solver_settings,
+    ))
+}
+
+/// Stable hash of the inputs the solver depends on: the settings, the stash (and how many cells it takes)
+/// and the queue.
+pub fn get_cache_key(
+    user_settings: &UserSettings,
+    stash: &BTreeMap<ModifierId, usize>,
+    stash_cell_count: usize,
+    queue: &[ModifierId],
+) -> u64 {
+    stable_hash(&(
+        get_settings_cache_key(user_settings),
+        stash,
+        stash_cell_count,
+        queue,
+    ))
+}
+
+/// Which of the thresholds of the solver the taken stash cells reach: a full stash enables the clearing mode,
+/// and the stash threshold the tier1 fillers.
+fn get_stash_pressure(user_settings: &UserSettings, stash_cell_count: usize) -> (bool, bool) {
+    (
+        stash_cell_count >= STASH_CAPACITY,
+        stash_cell_count >= user_settings.solver_settings.stash_threshold,
+    )
+}
+
 pub fn suggest_combo_cached(
     cache: &mut Cache,
     user_settings: &UserSettings,
     stash: &BTreeMap<ModifierId, usize>,
+    stash_cell_count: usize,
     queue: &[ModifierId],
-) -> Option<Vec<ModifierId>> {
+) -> Option<Suggestion> {
+    let settings_cache_key = stable_hash(&(
+        get_settings_cache_key(user_settings),
+        get_stash_pressure(user_settings, stash_cell_count),
+    ));
     match cache.last_suggested_combo {
-        Some(ref last_suggested_combo)
-            if last_suggested_combo
-                .iter()
-                .enumerate()
-                .all(|(index, &modifier_id)| {
-                    queue.get(index).is_none() && owns_modifier(stash, modifier_id)
-                        || queue.get(index) == Some(&modifier_id)
-                }) =>
+        // unless the settings or the stash pressure changed in between
+        Some((last_settings_cache_key, ref last_suggested_combo))
+            if last_settings_cache_key == settings_cache_key
+                && last_suggested_combo.combo.starts_with(queue)
+                && can_afford(stash, &last_suggested_combo.combo[queue.len()..]) =>
         {
             info!("suggesting same combo: {:?}", last_suggested_combo);
             Some(last_suggested_combo.clone())
         }
         _ => {
-            let mut hasher = DefaultHasher::new();
-            (user_settings, stash, queue).hash(&mut hasher);
-            let cache_key = hasher.finish();
-            cache
-                .suggested_combos
-                .entry(cache_key)
-                .or_insert_with(|| {
-                    cache.modified = true;
-                    suggest_combo(user_settings, stash, queue)
-                })
-                .as_ref()
-                .map(|combo| {
-                    cache.last_suggested_combo = Some(combo.clone());
-                    combo
-                })
-                .cloned()
+            let cache_key = get_cache_key(user_settings, stash, stash_cell_count, queue);
+            let suggested_combo = cache.get_or_insert_combo(cache_key, || {
+                suggest_combo(user_settings, stash, stash_cell_count, queue)
+            });
+            if let Some(suggested_combo) = &suggested_combo {
+                cache.last_suggested_combo = Some((settings_cache_key, suggested_combo.clone()));
+            }
+
+            suggested_combo
         }
     }
 }
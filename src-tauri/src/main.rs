@@ -3,95 +3,51 @@
     windows_subsystem = "windows"
 )]
 
-mod image;
-mod logic;
-mod utils;
-
-#[cfg(test)]
-mod tests;
-
-use crate::image::{process_image, Screenshot};
-use crate::logic::suggest_combo_cached;
-use dashmap::DashMap;
-use image::{ProcessImageResult, Rectangle, Vec2};
+use app::{cache, catalog, image, info_timed, inventory, logic, prices, utils};
+use cache::{Cache, CacheStats, Fingerprints};
+use catalog::ShareFormat;
+use image::{
+    process_image, CalibrationCell, CellContent, LayoutCalibration, ManualLayout,
+    ProcessImageResult, Rectangle, Screenshot, Vec2,
+};
+use inventory::StashInventory;
 use itertools::Itertools;
 use log::{error, info, warn, LevelFilter};
 use log4rs::append::console::ConsoleAppender;
 use log4rs::append::file::FileAppender;
 use log4rs::config::{Appender, Root};
 use log4rs::Config;
-use logic::{ModifierId, Modifiers, UserSettings, MODIFIERS};
+use logic::suggest_combo_cached;
+use logic::{
+    ComboId, ComboRuns, Hint, ModifierId, Modifiers, Reward, SolverSettings, UserSettings,
+    MODIFIERS,
+};
+use prices::PriceSnapshot;
 use retry::delay::Fixed;
 use retry::retry;
 use scrap::{Capturer, Display};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeSet, HashMap};
-use std::error::Error;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::ffi::c_void;
-use std::fs::File;
-use std::io::{BufReader, BufWriter};
+use std::sync::atomic::Ordering;
 use std::sync::Mutex;
+use std::time::Duration;
 use tauri::{GlobalShortcutManager, Manager};
 use thiserror::Error;
-use utils::{BincodeDiscSynchronized, DiscSynchronized};
+use utils::{DiscSynchronized, InstanceLock};
 #[cfg(target_os = "windows")]
 use windows::Win32::{
     Foundation::{BOOL, HWND},
     Graphics::Dwm::{DwmSetWindowAttribute, DWMWA_TRANSITIONS_FORCEDISABLED},
+    System::Console::{AttachConsole, ATTACH_PARENT_PROCESS},
 };
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
 const IGNORE_CACHE: bool = false;
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Cache {
-    pub version: String,
-    pub modified: bool,
-    pub layout: Option<HashMap<u8, Vec2>>,
-    pub images: DashMap<u64, Option<ModifierId>>,
-    pub suggested_combos: HashMap<u64, Option<Vec<ModifierId>>>,
-    pub last_suggested_combo: Option<Vec<ModifierId>>,
-}
-
-impl Cache {
-    fn clear(&mut self) {
-        self.layout = None;
-        self.images.clear();
-        self.suggested_combos.clear();
-        self.last_suggested_combo = None;
-    }
-}
-
-impl DiscSynchronized for Cache {
-    const FILE_NAME: &'static str = "archbroski\\.cache";
-
-    fn create_new() -> Self {
-        Self {
-            version: VERSION.into(),
-            modified: false,
-            layout: None,
-            images: DashMap::new(),
-            suggested_combos: HashMap::new(),
-            last_suggested_combo: None,
-        }
-    }
-
-    fn save_impl(&self, writer: &mut BufWriter<File>) -> Result<(), Box<dyn Error>> {
-        <Self as BincodeDiscSynchronized>::save_impl(self, writer)
-    }
+/// Gives the user time to bring the game, with the stash open, to the front before a calibration capture.
+const CALIBRATION_DELAY: Duration = Duration::from_secs(3);
 
-    fn is_valid(&self) -> bool {
-        self.version == VERSION
-    }
-
-    fn load_impl(reader: BufReader<File>) -> Result<Self, Box<dyn Error>> {
-        <Self as BincodeDiscSynchronized>::load_impl(reader)
-    }
-}
-
-impl BincodeDiscSynchronized for Cache {}
-
-#[derive(Clone, Copy, Debug, Serialize)]
+#[derive(Clone, Debug, Serialize)]
 #[serde(tag = "type")]
 enum ActivationState {
     Hidden,
@@ -102,6 +58,9 @@ enum ActivationState {
     Computed {
         stash_area: Rectangle,
         suggested_cell_area: Rectangle,
+        hints: Vec<Hint>,
+        /// Stash cells which are neither empty nor recognized, left out of the stash the combo is suggested for.
+        unknown_cell_count: usize,
     },
     DetectionError,
     LogicError,
@@ -209,6 +168,38 @@ fn show_settings_window(app: &tauri::AppHandle) {
     }
 }
 
+fn take_screenshot() -> Result<Screenshot, ActivationError> {
+    Display::primary()
+        .map_err(|err| {
+            error!("failed to get primary display: {:?}", err);
+            ActivationError::DetectionError
+        })
+        .and_then(|display| {
+            Capturer::new(display).map_err(|err| {
+                error!("failed to create a capturer: {:?}", err);
+                ActivationError::DetectionError
+            })
+        })
+        .and_then(|mut capturer| {
+            retry(Fixed::from_millis(50).take(10), || {
+                let capturer_width = capturer.width();
+                let capturer_height = capturer.height();
+                capturer
+                    .frame()
+                    .map(|frame| (frame.to_vec(), capturer_width, capturer_height))
+            })
+            .map_err(|err| {
+                error!("failed to take a screenshot: {:?}", err);
+                ActivationError::DetectionError
+            })
+        })
+        .map(|(buffer, width, height)| Screenshot {
+            buffer,
+            width,
+            height,
+        })
+}
+
 fn activate(app: &tauri::AppHandle) {
     if let Ok(mut activation_state) = app.state::<Mutex<(u64, ActivationState)>>().try_lock() {
         if !matches!(activation_state.1, ActivationState::Hidden) {
@@ -223,37 +214,22 @@ fn activate(app: &tauri::AppHandle) {
         info!("trying to activate with id: {:?}", activation_id);
         app.get_window("overlay")
             .unwrap()
-            .emit("update", activation_state.1)
+            .emit("update", activation_state.1.clone())
             .unwrap();
         drop(activation_state);
 
         let app = app.clone();
         tauri::async_runtime::spawn(async move {
-            match Display::primary()
-                .map_err(|err| {
-                    error!("failed to get primary display: {:?}", err);
-                    ActivationError::DetectionError
-                })
-                .and_then(|display| {
-                    Capturer::new(display).map_err(|err| {
-                        error!("failed to create a capturer: {:?}", err);
-                        ActivationError::DetectionError
-                    })
-                })
-                .and_then(|mut capturer| {
-                    retry(Fixed::from_millis(50).take(10), || {
-                        let capturer_width = capturer.width();
-                        let capturer_height = capturer.height();
-                        capturer
-                            .frame()
-                            .map(|frame| (frame.to_vec(), capturer_width, capturer_height))
-                    })
-                    .map_err(|err| {
-                        error!("failed to take a screenshot: {:?}", err);
-                        ActivationError::DetectionError
-                    })
-                })
-                .and_then(|(buffer, width, height)| {
+            match take_screenshot()
+                .and_then(|screenshot| {
+                    let manual_layout = app
+                        .state::<Result<Mutex<UserSettings>, &'static str>>()
+                        .as_ref()
+                        .unwrap()
+                        .lock()
+                        .unwrap()
+                        .manual_layout
+                        .clone();
                     let cache_state = app.state::<Result<Mutex<Cache>, &'static str>>();
                     let mut cache = cache_state.as_ref().unwrap().lock().unwrap();
                     cache.modified = false;
@@ -261,26 +237,35 @@ fn activate(app: &tauri::AppHandle) {
                         cache.clear();
                     }
 
-                    process_image(
-                        &mut cache,
-                        Screenshot {
-                            buffer,
-                            width,
-                            height,
-                        },
+                    info_timed!(
+                        "process_image",
+                        process_image(&mut cache, screenshot, manual_layout.as_ref())
                     )
                     .ok_or(ActivationError::DetectionError)
                 })
                 .and_then(
                     |ProcessImageResult {
                          stash_area,
-                         stash_modifier_ids,
-                         queue_modifier_ids,
+                         stash_cells,
+                         queue_contents,
+                         ..
                      }| {
-                        let stash_areas = stash_modifier_ids.iter().fold(
+                        // the suggestion depends on the exact queue, unlike on the exact stash
+                        if queue_contents.contains(&CellContent::Unknown) {
+                            warn!("unknown modifier in the queue");
+                            return Err(ActivationError::DetectionError);
+                        }
+
+                        let stash_inventory = StashInventory::new(stash_cells.values().copied());
+                        let unknown_cell_count = stash_inventory.get_unknown_cell_count();
+                        if unknown_cell_count > 0 {
+                            warn!("unknown modifiers in {} stash cells", unknown_cell_count);
+                        }
+
+                        let stash_areas = stash_cells.iter().fold(
                             HashMap::<ModifierId, BTreeSet<Rectangle>>::new(),
-                            |mut stash_by_modifier_ids, (&cell_area, &modifier_id)| {
-                                if let Some(modifier_id) = modifier_id {
+                            |mut stash_by_modifier_ids, (&cell_area, cell)| {
+                                if let Some(modifier_id) = cell.content.get_modifier_id() {
                                     stash_by_modifier_ids
                                         .entry(modifier_id)
                                         .or_default()
@@ -290,17 +275,11 @@ fn activate(app: &tauri::AppHandle) {
                                 stash_by_modifier_ids
                             },
                         );
-                        let stash = stash_modifier_ids
-                            .values()
-                            .filter_map(|modifier_id| modifier_id.as_ref())
-                            .copied()
-                            .counts()
-                            .into_iter()
-                            .collect();
-                        let queue = queue_modifier_ids
+                        let stash = stash_inventory.get_modifier_counts();
+                        let stash_cell_count = stash_inventory.get_taken_cell_count();
+                        let queue = queue_contents
                             .iter()
-                            .filter_map(|modifier_id| modifier_id.as_ref())
-                            .copied()
+                            .filter_map(CellContent::get_modifier_id)
                             .collect_vec();
 
                         let cache_state = app.state::<Result<Mutex<Cache>, &'static str>>();
@@ -309,28 +288,63 @@ fn activate(app: &tauri::AppHandle) {
                         let user_settings_state =
                             app.state::<Result<Mutex<UserSettings>, &'static str>>();
                         let user_settings = user_settings_state.as_ref().unwrap().lock().unwrap();
-                        suggest_combo_cached(&mut cache, &user_settings, &stash, &queue)
+                        let combo_runs_state =
+                            app.state::<Result<Mutex<ComboRuns>, &'static str>>();
+                        let mut combo_runs = combo_runs_state.as_ref().unwrap().lock().unwrap();
+                        let previous_combo_runs = combo_runs.clone();
+                        // an exhausted combo leaves the roster, which invalidates the last suggestion
+                        if let Some(combo_id) = combo_runs.observe_queue(&user_settings, &queue) {
+                            info!("counted a run of combo: {:?}", combo_id);
+                        }
+
+                        let user_settings = combo_runs.get_pending_user_settings(&user_settings);
+                        let suggestion = info_timed!(
+                            "suggest_combo",
+                            suggest_combo_cached(
+                                &mut cache,
+                                &user_settings,
+                                &stash,
+                                stash_cell_count,
+                                &queue
+                            )
+                        );
+                        if let Some(suggestion) = &suggestion {
+                            combo_runs.observe_suggestion(&user_settings, &queue, suggestion);
+                        }
+
+                        if *combo_runs != previous_combo_runs {
+                            let saved_combo_runs = combo_runs.clone();
+                            std::thread::spawn(move || {
+                                let _ = saved_combo_runs.save(); // TODO handle error
+                            });
+                        }
+
+                        suggestion
                             .ok_or(ActivationError::LogicError)
-                            .map(|combo| combo[queue.len()])
-                            .and_then(|suggested_modifier_id| {
+                            .map(|suggestion| (suggestion.combo[queue.len()], suggestion.hints))
+                            .and_then(|(suggested_modifier_id, hints)| {
+                                cache.evict();
                                 if cache.modified {
+                                    cache.recency_modified.store(false, Ordering::Relaxed);
                                     cache.save().map_err(|err| {
                                         error!("failed to sync cache: {:?}", err);
                                         ActivationError::DetectionError
                                     })?;
                                 }
 
-                                Ok(suggested_modifier_id)
+                                Ok((suggested_modifier_id, hints))
                             })
-                            .map(|suggested_modifier_id| {
+                            .map(|(suggested_modifier_id, hints)| {
                                 (
                                     stash_area,
                                     *stash_areas[&suggested_modifier_id].iter().next().unwrap(),
+                                    hints,
+                                    unknown_cell_count,
                                 )
                             })
                     },
                 ) {
-                Ok((stash_area, suggested_cell_area)) => {
+                Ok((stash_area, suggested_cell_area, hints, unknown_cell_count)) => {
                     let activation_state_state = app.state::<Mutex<(u64, ActivationState)>>();
                     let mut activation_state = activation_state_state.lock().unwrap();
                     if let ActivationState::Computing { id } = activation_state.1 {
@@ -339,10 +353,12 @@ fn activate(app: &tauri::AppHandle) {
                             activation_state.1 = ActivationState::Computed {
                                 stash_area,
                                 suggested_cell_area,
+                                hints,
+                                unknown_cell_count,
                             };
                             app.get_window("overlay")
                                 .unwrap()
-                                .emit("update", activation_state.1)
+                                .emit("update", activation_state.1.clone())
                                 .unwrap();
                         }
                     }
@@ -359,7 +375,7 @@ fn activate(app: &tauri::AppHandle) {
                             };
                             app.get_window("overlay")
                                 .unwrap()
-                                .emit("update", activation_state.1)
+                                .emit("update", activation_state.1.clone())
                                 .unwrap();
                         }
                     }
@@ -386,12 +402,14 @@ fn get_monitor_size(window: tauri::Window) -> (u32, u32, f64) {
 fn get_error_message(
     user_settings_state: tauri::State<'_, Result<Mutex<UserSettings>, &'static str>>,
     cache_state: tauri::State<'_, Result<Mutex<Cache>, &'static str>>,
+    combo_runs_state: tauri::State<'_, Result<Mutex<ComboRuns>, &'static str>>,
 ) -> Option<&'static str> {
     user_settings_state
         .as_ref()
         .err()
         .copied()
         .or_else(|| cache_state.as_ref().err().copied())
+        .or_else(|| combo_runs_state.as_ref().err().copied())
 }
 
 #[tauri::command(async)]
@@ -411,7 +429,15 @@ fn set_user_settings(
     app: tauri::AppHandle,
     user_settings_state: tauri::State<'_, Result<Mutex<UserSettings>, &'static str>>,
     user_settings: UserSettings,
-) {
+) -> Result<(), String> {
+    user_settings
+        .solver_settings
+        .validate()
+        .map_err(|err| err.to_string())?;
+    if !user_settings.is_valid() {
+        return Err("invalid user settings".into());
+    }
+
     let saved_user_settings = user_settings.clone();
     std::thread::spawn(move || {
         let _ = saved_user_settings.save(); // TODO handle error
@@ -430,6 +456,292 @@ fn set_user_settings(
             activate(&app);
         })
         .unwrap();
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ComboConstraints {
+    weight: u32,
+    max_runs: Option<u32>,
+    enabled: bool,
+    filler_tolerance: Option<usize>,
+}
+
+#[tauri::command(async)]
+fn set_combo_constraints(
+    user_settings_state: tauri::State<'_, Result<Mutex<UserSettings>, &'static str>>,
+    combo_id: ComboId,
+    constraints: ComboConstraints,
+) -> Result<UserSettings, String> {
+    if constraints.weight == 0 {
+        return Err("the weight must be positive".to_owned());
+    }
+
+    let mut user_settings_guard = user_settings_state.as_ref().unwrap().lock().unwrap();
+    let mut user_settings = user_settings_guard.clone();
+    let queue_length = user_settings.solver_settings.queue_length;
+    let combo = user_settings
+        .combo_catalog
+        .iter_mut()
+        .find(|combo| combo.id == combo_id)
+        .ok_or_else(|| format!("unknown combo: {}", combo_id))?;
+    combo.weight = constraints.weight;
+    combo.max_runs = constraints.max_runs;
+    combo.enabled = constraints.enabled;
+    combo.filler_tolerance = constraints
+        .filler_tolerance
+        .map(|filler_tolerance| filler_tolerance.min(queue_length));
+
+    let saved_user_settings = user_settings.clone();
+    std::thread::spawn(move || {
+        let _ = saved_user_settings.save(); // TODO handle error
+    });
+
+    *user_settings_guard = user_settings.clone();
+    Ok(user_settings)
+}
+
+#[tauri::command(async)]
+fn set_solver_settings(
+    user_settings_state: tauri::State<'_, Result<Mutex<UserSettings>, &'static str>>,
+    solver_settings: SolverSettings,
+) -> Result<UserSettings, String> {
+    solver_settings.validate().map_err(|err| err.to_string())?;
+
+    let mut user_settings_guard = user_settings_state.as_ref().unwrap().lock().unwrap();
+    let mut user_settings = user_settings_guard.clone();
+    user_settings.solver_settings = solver_settings;
+
+    let saved_user_settings = user_settings.clone();
+    std::thread::spawn(move || {
+        let _ = saved_user_settings.save(); // TODO handle error
+    });
+
+    *user_settings_guard = user_settings.clone();
+    Ok(user_settings)
+}
+
+#[tauri::command(async)]
+fn get_combo_runs(
+    combo_runs_state: tauri::State<'_, Result<Mutex<ComboRuns>, &'static str>>,
+) -> ComboRuns {
+    combo_runs_state.as_ref().unwrap().lock().unwrap().clone()
+}
+
+#[tauri::command(async)]
+fn reset_combo_runs(
+    combo_runs_state: tauri::State<'_, Result<Mutex<ComboRuns>, &'static str>>,
+    combo_id: ComboId,
+) -> ComboRuns {
+    let mut combo_runs = combo_runs_state.as_ref().unwrap().lock().unwrap();
+    combo_runs.reset(combo_id);
+
+    let saved_combo_runs = combo_runs.clone();
+    std::thread::spawn(move || {
+        let _ = saved_combo_runs.save(); // TODO handle error
+    });
+
+    saved_combo_runs
+}
+
+#[tauri::command(async)]
+fn get_cache_stats(
+    cache_state: tauri::State<'_, Result<Mutex<Cache>, &'static str>>,
+) -> CacheStats {
+    cache_state.as_ref().unwrap().lock().unwrap().get_stats()
+}
+
+/// Discards the cached values derived from templates or prices which changed since.
+fn invalidate_stale_cache(cache: &mut Cache) {
+    cache.invalidate_stale(Fingerprints::current());
+    if cache.modified {
+        if let Err(err) = cache.save() {
+            error!("failed to sync cache: {:?}", err);
+        }
+    }
+}
+
+/// Reads the price file, if any, and values the rewards accordingly (or by their defaults without one). A file
+/// which fails to load is ignored too, rather than keeping the previous prices, since the settings then show
+/// the defaults.
+fn load_prices() -> Result<Option<PriceSnapshot>, String> {
+    let price_snapshot = prices::load_price_snapshot().map_err(|err| err.to_string());
+    logic::set_reward_values(prices::get_reward_values(
+        price_snapshot
+            .as_ref()
+            .ok()
+            .and_then(Option::as_ref)
+            .map_or(&BTreeMap::new(), |price_snapshot| &price_snapshot.prices),
+    ));
+    price_snapshot
+}
+
+#[tauri::command(async)]
+fn get_price_snapshot(
+    price_snapshot_state: tauri::State<'_, Mutex<Result<Option<PriceSnapshot>, String>>>,
+) -> Result<Option<PriceSnapshot>, String> {
+    price_snapshot_state.lock().unwrap().clone()
+}
+
+#[tauri::command(async)]
+fn reload_prices(
+    price_snapshot_state: tauri::State<'_, Mutex<Result<Option<PriceSnapshot>, String>>>,
+    cache_state: tauri::State<'_, Result<Mutex<Cache>, &'static str>>,
+) -> Result<Option<PriceSnapshot>, String> {
+    let price_snapshot = load_prices();
+    if let Err(err) = &price_snapshot {
+        warn!("failed to load prices: {}", err);
+    }
+
+    *price_snapshot_state.lock().unwrap() = price_snapshot.clone();
+    invalidate_stale_cache(&mut cache_state.as_ref().unwrap().lock().unwrap());
+    price_snapshot
+}
+
+#[tauri::command(async)]
+fn capture_calibration_cells(
+    window: tauri::Window,
+    user_settings_state: tauri::State<'_, Result<Mutex<UserSettings>, &'static str>>,
+    cache_state: tauri::State<'_, Result<Mutex<Cache>, &'static str>>,
+    calibration_cells_state: tauri::State<'_, Mutex<Vec<CalibrationCell>>>,
+) -> Result<Vec<CalibrationCell>, String> {
+    let _ = window.minimize();
+    std::thread::sleep(CALIBRATION_DELAY);
+    let screenshot = take_screenshot();
+    let _ = window.unminimize();
+    let _ = window.set_focus();
+
+    let manual_layout = user_settings_state
+        .as_ref()
+        .unwrap()
+        .lock()
+        .unwrap()
+        .manual_layout
+        .clone();
+    let mut cache = cache_state.as_ref().unwrap().lock().unwrap();
+    let calibration_cells = screenshot
+        .and_then(|screenshot| {
+            image::get_calibration_cells(&mut cache, screenshot, manual_layout.as_ref())
+                .ok_or(ActivationError::DetectionError)
+        })
+        .map_err(|err| err.to_string())?;
+    *calibration_cells_state.lock().unwrap() = calibration_cells.clone();
+    Ok(calibration_cells)
+}
+
+/// Stores the captured cells as the templates of the modifiers they were labeled with (by index, `None`
+/// skipping a cell).
+#[tauri::command(async)]
+fn save_user_templates(
+    cache_state: tauri::State<'_, Result<Mutex<Cache>, &'static str>>,
+    calibration_cells_state: tauri::State<'_, Mutex<Vec<CalibrationCell>>>,
+    labels: Vec<Option<ModifierId>>,
+) -> Result<Vec<ModifierId>, String> {
+    // kept until saved, so that the labels can be fixed if they are rejected
+    let calibration_cells = calibration_cells_state.lock().unwrap().clone();
+    image::save_user_templates(labels.into_iter().zip(&calibration_cells).filter_map(
+        |(modifier_id, cell)| {
+            modifier_id
+                .filter(|modifier_id| MODIFIERS.by_id.contains_key(modifier_id))
+                .map(|modifier_id| (modifier_id, cell))
+        },
+    ))
+    .map_err(|err| err.to_string())?;
+    calibration_cells_state.lock().unwrap().clear();
+    invalidate_stale_cache(&mut cache_state.as_ref().unwrap().lock().unwrap());
+    Ok(image::get_user_template_modifier_ids())
+}
+
+#[tauri::command(async)]
+fn get_user_templates() -> Vec<ModifierId> {
+    image::get_user_template_modifier_ids()
+}
+
+#[tauri::command(async)]
+fn clear_user_templates(
+    cache_state: tauri::State<'_, Result<Mutex<Cache>, &'static str>>,
+) -> Result<Vec<ModifierId>, String> {
+    image::clear_user_templates().map_err(|err| err.to_string())?;
+    invalidate_stale_cache(&mut cache_state.as_ref().unwrap().lock().unwrap());
+    Ok(image::get_user_template_modifier_ids())
+}
+
+#[tauri::command(async)]
+fn capture_layout_calibration(window: tauri::Window) -> Result<LayoutCalibration, String> {
+    let _ = window.minimize();
+    std::thread::sleep(CALIBRATION_DELAY);
+    let screenshot = take_screenshot();
+    let _ = window.unminimize();
+    let _ = window.set_focus();
+
+    screenshot
+        .map(image::get_layout_calibration)
+        .map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+fn set_manual_layout(
+    user_settings_state: tauri::State<'_, Result<Mutex<UserSettings>, &'static str>>,
+    manual_layout: Option<ManualLayout>,
+) -> Result<UserSettings, String> {
+    if !manual_layout.as_ref().map_or(true, ManualLayout::is_valid) {
+        return Err("invalid manual layout".into());
+    }
+
+    let mut user_settings_guard = user_settings_state.as_ref().unwrap().lock().unwrap();
+    let mut user_settings = user_settings_guard.clone();
+    user_settings.manual_layout = manual_layout;
+
+    let saved_user_settings = user_settings.clone();
+    std::thread::spawn(move || {
+        let _ = saved_user_settings.save(); // TODO handle error
+    });
+
+    *user_settings_guard = user_settings.clone();
+    Ok(user_settings)
+}
+
+fn clear_cache(app: &tauri::AppHandle) {
+    if let Ok(cache) = app.state::<Result<Mutex<Cache>, &'static str>>().as_ref() {
+        let mut cache = cache.lock().unwrap();
+        cache.clear();
+        if let Err(err) = cache.save() {
+            error!("failed to sync cache: {:?}", err);
+        } else {
+            info!("cleared cache");
+        }
+    }
+}
+
+#[tauri::command(async)]
+fn export_combos(
+    user_settings_state: tauri::State<'_, Result<Mutex<UserSettings>, &'static str>>,
+    combo_ids: Vec<ComboId>,
+    format: ShareFormat,
+) -> Result<String, String> {
+    let user_settings = user_settings_state.as_ref().unwrap().lock().unwrap();
+    catalog::export_combos(&user_settings, &combo_ids, format).map_err(|err| err.to_string())
+}
+
+#[tauri::command(async)]
+fn import_combos(
+    user_settings_state: tauri::State<'_, Result<Mutex<UserSettings>, &'static str>>,
+    data: String,
+) -> Result<UserSettings, String> {
+    let mut user_settings_guard = user_settings_state.as_ref().unwrap().lock().unwrap();
+    let mut user_settings = user_settings_guard.clone();
+    let combo_ids =
+        catalog::import_combos(&mut user_settings, &data).map_err(|err| err.to_string())?;
+    info!("imported combos: {:?}", combo_ids);
+
+    let saved_user_settings = user_settings.clone();
+    std::thread::spawn(move || {
+        let _ = saved_user_settings.save(); // TODO handle error
+    });
+
+    *user_settings_guard = user_settings.clone();
+    Ok(user_settings)
 }
 
 #[tauri::command(async)]
@@ -437,6 +749,11 @@ fn get_modifiers() -> Modifiers {
     MODIFIERS.clone()
 }
 
+#[tauri::command(async)]
+fn get_rewards() -> Vec<Reward> {
+    logic::get_rewards()
+}
+
 #[tauri::command(async)]
 fn hide_overlay_window(app: tauri::AppHandle, overlay_window: tauri::Window) {
     overlay_window.hide().unwrap();
@@ -446,19 +763,74 @@ fn hide_overlay_window(app: tauri::AppHandle, overlay_window: tauri::Window) {
         .1 = ActivationState::Hidden;
 }
 
+/// Saves the cache if only the recency of its entries changed since the last save, which isn't worth a save on
+/// every activation, but keeps the eviction order across restarts.
+fn save_cache_recency(app: &tauri::AppHandle) {
+    if let Ok(cache) = app.state::<Result<Mutex<Cache>, &'static str>>().as_ref() {
+        let cache = cache.lock().unwrap();
+        if cache.recency_modified.swap(false, Ordering::Relaxed) {
+            if let Err(err) = cache.save() {
+                error!("failed to sync cache: {:?}", err);
+            }
+        }
+    }
+}
+
 #[tauri::command(async)]
 fn exit(app: tauri::AppHandle) {
+    save_cache_recency(&app);
     app.exit(0);
 }
 
+/// Release builds have no console of their own, so the CLI writes to the one it was started from, if any.
+#[cfg(target_os = "windows")]
+fn attach_parent_console() {
+    unsafe {
+        let _ = AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+fn attach_parent_console() {}
+
 fn main() {
+    let args = std::env::args().skip(1).collect_vec();
+    if !args.is_empty() {
+        attach_parent_console();
+    }
+
+    if let Some(exit_code) = catalog::run_cli(&args) {
+        std::process::exit(exit_code);
+    }
+
+    // released on exit; keeps the CLI from importing combos meanwhile
+    let _instance_lock = InstanceLock::acquire().map_err(|err| {
+        eprintln!("failed to lock the app files: {}", err);
+    });
+
     tauri::Builder::default()
         .invoke_handler(tauri::generate_handler![
             get_monitor_size,
             get_error_message,
             get_user_settings,
             set_user_settings,
+            set_combo_constraints,
+            set_solver_settings,
+            get_combo_runs,
+            reset_combo_runs,
+            get_cache_stats,
+            export_combos,
+            import_combos,
             get_modifiers,
+            get_rewards,
+            get_price_snapshot,
+            reload_prices,
+            capture_calibration_cells,
+            save_user_templates,
+            get_user_templates,
+            clear_user_templates,
+            capture_layout_calibration,
+            set_manual_layout,
             hide_overlay_window,
             exit,
         ])
@@ -470,6 +842,7 @@ fn main() {
                 .with_menu(
                     tauri::SystemTrayMenu::new()
                         .add_item(tauri::CustomMenuItem::new("settings", "Settings"))
+                        .add_item(tauri::CustomMenuItem::new("clear_cache", "Clear cache"))
                         .add_item(tauri::CustomMenuItem::new("quit", "Quit")),
                 ),
         )
@@ -477,9 +850,13 @@ fn main() {
             if let tauri::SystemTrayEvent::MenuItemClick { id, .. } = event {
                 match id.as_str() {
                     "settings" => show_settings_window(app),
+                    "clear_cache" => clear_cache(app),
                     "quit" => {
                         let app = app.clone();
-                        std::thread::spawn(move || app.exit(0));
+                        std::thread::spawn(move || {
+                            save_cache_recency(&app);
+                            app.exit(0);
+                        });
                     }
                     _ => {}
                 }
@@ -487,6 +864,16 @@ fn main() {
         })
         .setup(|app| {
             init_logger();
+            // before loading the cache too, since the cached images depend on the templates
+            image::reload_user_templates();
+            // before loading the cache, so that combos valued with outdated prices are discarded
+            let price_snapshot = load_prices();
+            if let Err(err) = &price_snapshot {
+                warn!("failed to load prices: {}", err);
+            }
+
+            app.manage(Mutex::new(price_snapshot));
+            app.manage(Mutex::new(Vec::<CalibrationCell>::new()));
             app.manage(
                 UserSettings::load_or_new_saved()
                     .map(Mutex::new)
@@ -494,12 +881,26 @@ fn main() {
             );
             app.manage(
                 Cache::load_or_new_saved()
+                    .and_then(|mut cache| {
+                        cache.invalidate_stale(Fingerprints::current());
+                        if cache.modified {
+                            cache.save()?;
+                        }
+
+                        Ok(cache)
+                    })
                     .map(Mutex::new)
                     .map_err(|_| "failed_to_load_cache"),
             );
+            app.manage(
+                ComboRuns::load_or_new_saved()
+                    .map(Mutex::new)
+                    .map_err(|_| "failed_to_load_combo_runs"),
+            );
             if let Some(err) = get_error_message(
                 app.state::<Result<Mutex<UserSettings>, &'static str>>(),
                 app.state::<Result<Mutex<Cache>, &'static str>>(),
+                app.state::<Result<Mutex<ComboRuns>, &'static str>>(),
             ) {
                 error!("failed to start: {}", err);
                 create_error_window(&app.handle());
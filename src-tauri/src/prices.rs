@@ -0,0 +1,183 @@
+//! Import of reward prices from a price snapshot the user drops in the config directory, e.g. exported from a
+//! pricing tool. Nothing is ever fetched online.
+
+use crate::logic::{get_rewards, Reward, DEFAULT_REWARD_VALUES};
+use dirs::config_dir;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{metadata, read_to_string};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+use thiserror::Error;
+
+/// Price files looked up in the config directory, in order.
+pub const PRICE_FILE_NAMES: [(&str, PriceFormat); 2] = [
+    ("archbroski\\prices.json", PriceFormat::Json),
+    ("archbroski\\prices.csv", PriceFormat::Csv),
+];
+
+const CSV_SEPARATOR: char = ',';
+
+const CSV_COMMENT_PREFIX: char = '#';
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PriceFormat {
+    /// An object mapping reward names to chaos values, e.g. `{"Scarab": 4.5}`.
+    Json,
+    /// One `reward,chaos value` line per reward, with an optional header line.
+    Csv,
+}
+
+#[derive(Error, Debug, PartialEq)]
+pub enum PriceError {
+    #[error("cannot read the price file: {0}")]
+    Io(String),
+    #[error("malformed json: {0}")]
+    MalformedJson(String),
+    #[error("malformed line {0}: {1:?}")]
+    MalformedLine(usize, String),
+    #[error("unknown reward: {0:?}")]
+    UnknownReward(String),
+    #[error("invalid price for {0:?}: {1}")]
+    InvalidPrice(Reward, f32),
+    #[error("more than one price for {0:?}")]
+    DuplicatePrice(Reward),
+    #[error("no prices")]
+    Empty,
+}
+
+/// Prices read from a price file.
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceSnapshot {
+    pub file_name: String,
+    /// Last modification of the price file, in seconds since the unix epoch.
+    pub modified_at: u64,
+    /// Chaos value of each priced reward.
+    pub prices: BTreeMap<Reward, f32>,
+}
+
+/// Reward names are matched ignoring case and whitespace, so that `Divination Card` matches
+/// [`Reward::DivinationCard`].
+fn get_reward(reward_name: &str) -> Result<Reward, PriceError> {
+    let normalized_reward_name = reward_name
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>();
+    get_rewards()
+        .into_iter()
+        .find(|reward| format!("{:?}", reward).eq_ignore_ascii_case(&normalized_reward_name))
+        .ok_or_else(|| PriceError::UnknownReward(reward_name.trim().to_owned()))
+}
+
+fn collect_prices(
+    prices: impl IntoIterator<Item = Result<(String, f32), PriceError>>,
+) -> Result<BTreeMap<Reward, f32>, PriceError> {
+    let prices = prices.into_iter().try_fold(
+        BTreeMap::new(),
+        |mut prices, price| -> Result<_, PriceError> {
+            let (reward_name, price) = price?;
+            let reward = get_reward(&reward_name)?;
+            if !price.is_finite() || price <= 0.0 {
+                return Err(PriceError::InvalidPrice(reward, price));
+            }
+
+            if prices.insert(reward, price).is_some() {
+                return Err(PriceError::DuplicatePrice(reward));
+            }
+
+            Ok(prices)
+        },
+    )?;
+    if prices.is_empty() {
+        return Err(PriceError::Empty);
+    }
+
+    Ok(prices)
+}
+
+fn parse_csv(data: &str) -> impl Iterator<Item = Result<(String, f32), PriceError>> + '_ {
+    data.lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line.trim()))
+        .filter(|(_, line)| !line.is_empty() && !line.starts_with(CSV_COMMENT_PREFIX))
+        .enumerate()
+        .filter_map(|(index, (line_number, line))| {
+            let (reward_name, price) = match line.split_once(CSV_SEPARATOR) {
+                Some((reward_name, price)) => (reward_name.trim(), price.trim()),
+                None => return Some(Err(PriceError::MalformedLine(line_number, line.to_owned()))),
+            };
+            match price.parse::<f32>() {
+                Ok(price) => Some(Ok((reward_name.to_owned(), price))),
+                // header line
+                Err(_) if index == 0 => None,
+                Err(_) => Some(Err(PriceError::MalformedLine(line_number, line.to_owned()))),
+            }
+        })
+}
+
+/// Parses the chaos value of each reward listed in a price file.
+pub fn parse_prices(data: &str, format: PriceFormat) -> Result<BTreeMap<Reward, f32>, PriceError> {
+    match format {
+        PriceFormat::Json => collect_prices(
+            serde_json::from_str::<BTreeMap<String, f32>>(data)
+                .map_err(|err| PriceError::MalformedJson(err.to_string()))?
+                .into_iter()
+                .map(Ok),
+        ),
+        PriceFormat::Csv => collect_prices(parse_csv(data)),
+    }
+}
+
+/// Value of each reward given the imported prices. Rewards missing from the prices keep their default value,
+/// scaled by the ratio between the prices and the default values of the priced rewards.
+pub fn get_reward_values(prices: &BTreeMap<Reward, f32>) -> HashMap<Reward, f32> {
+    let scale = if prices.is_empty() {
+        1.0
+    } else {
+        prices.values().sum::<f32>()
+            / prices
+                .keys()
+                .map(|reward| DEFAULT_REWARD_VALUES[reward] as f32)
+                .sum::<f32>()
+    };
+    DEFAULT_REWARD_VALUES
+        .iter()
+        .map(|(&reward, &value)| {
+            (
+                reward,
+                prices.get(&reward).copied().unwrap_or(value as f32 * scale),
+            )
+        })
+        .collect()
+}
+
+/// Reads the first price file found in the config directory, if any.
+pub fn load_price_snapshot() -> Result<Option<PriceSnapshot>, PriceError> {
+    let config_dir =
+        config_dir().ok_or_else(|| PriceError::Io("cannot find home directory".into()))?;
+    for (file_name, format) in PRICE_FILE_NAMES {
+        let path = config_dir.join(PathBuf::from(file_name));
+        if !path.is_file() {
+            continue;
+        }
+
+        let modified_at = metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .map_err(|err| PriceError::Io(err.to_string()))?
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default();
+        let data = read_to_string(&path).map_err(|err| PriceError::Io(err.to_string()))?;
+        return Ok(Some(PriceSnapshot {
+            file_name: path
+                .file_name()
+                .map(|file_name| file_name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            modified_at,
+            prices: parse_prices(&data, format)?,
+        }));
+    }
+
+    Ok(None)
+}
@@ -0,0 +1,166 @@
+//! Simulation of the rewards dropped by the monsters a combo is applied to.
+//!
+//! The modifiers of the queue are applied to the rare monsters one after another: the first monster gets the
+//! first modifier, the second one the first two modifiers, and so on. The rewards of each monster are the sum
+//! of the rewards of its modifiers, transformed by their effects in queue order.
+
+use std::collections::BTreeMap;
+
+use crate::logic::{get_reward_value, Effect, Modifier, ModifierId, Reward, MODIFIERS};
+use crate::utils::Rng;
+
+/// Bumped whenever the reward model changes, invalidating the cached combos.
+pub const MODEL_VERSION: u32 = 1;
+
+/// Expected value of the best of `draw_count` reward items, relative to an average one. The value of a
+/// reward item is modeled as uniformly distributed between nothing and twice the average value.
+pub fn get_best_of_multiplier(draw_count: usize) -> f32 {
+    2.0 * draw_count as f32 / (draw_count + 1) as f32
+}
+
+/// Mean and variance of the value of a combo, estimated by sampling its rewards.
+#[derive(PartialEq, Debug, Clone, Copy, Default)]
+pub struct ValueEstimate {
+    pub mean: f32,
+    pub variance: f32,
+}
+
+impl ValueEstimate {
+    pub fn get_std_dev(&self) -> f32 {
+        self.variance.sqrt()
+    }
+}
+
+/// The rewards of a single monster.
+#[derive(PartialEq, Eq, Debug, Clone, Default)]
+pub struct MonsterRewards {
+    /// Number of reward items of each type.
+    pub counts: BTreeMap<Reward, usize>,
+    /// Number of times each reward item is rerolled, keeping the best one.
+    pub reroll_count: usize,
+}
+
+impl MonsterRewards {
+    /// Applies the modifiers of a monster in queue order. A conversion turns the rewards gathered so far into
+    /// the converted type, while additional and doubled rewards apply to all rewards of the monster, in this
+    /// order.
+    pub fn new<'a>(modifiers: impl IntoIterator<Item = &'a Modifier>) -> Self {
+        let mut counts = BTreeMap::<Reward, usize>::new();
+        let mut additional_reward_count = 0;
+        let mut doubled_reward_count = 0;
+        let mut reroll_count = 0;
+        for modifier in modifiers {
+            for (&reward, &count) in &modifier.rewards {
+                *counts.entry(reward).or_default() += count;
+            }
+
+            match modifier.effect {
+                Some(Effect::Convert { to }) => {
+                    let count = counts.values().sum::<usize>();
+                    counts.clear();
+                    if count > 0 {
+                        counts.insert(to, count);
+                    }
+                }
+                Some(Effect::AdditionalReward) => additional_reward_count += 1,
+                Some(Effect::DoubledReward) => doubled_reward_count += 1,
+                Some(Effect::Reroll { count }) => reroll_count += count,
+                None => {}
+            }
+        }
+
+        for count in counts.values_mut() {
+            *count = (*count + additional_reward_count) << doubled_reward_count;
+        }
+
+        Self {
+            counts,
+            reroll_count,
+        }
+    }
+
+    /// Expected value of the rewards of each type.
+    pub fn get_expected_values(&self) -> BTreeMap<Reward, f32> {
+        let multiplier = get_best_of_multiplier(self.reroll_count + 1);
+        self.counts
+            .iter()
+            .map(|(&reward, &count)| (reward, get_reward_value(reward) * count as f32 * multiplier))
+            .collect()
+    }
+
+    /// Samples the value of the rewards of the counted types. Each reward item is drawn once, plus once per
+    /// reroll, keeping the best draw.
+    pub fn sample_value(&self, is_counted: &impl Fn(&Reward) -> bool, rng: &mut Rng) -> f64 {
+        self.counts
+            .iter()
+            .filter(|(reward, _)| is_counted(reward))
+            .map(|(reward, &count)| {
+                let max_value = 2.0 * get_reward_value(*reward) as f64;
+                (0..count)
+                    .map(|_| {
+                        (0..=self.reroll_count)
+                            .map(|_| rng.next_f64() * max_value)
+                            .fold(0.0, f64::max)
+                    })
+                    .sum::<f64>()
+            })
+            .sum()
+    }
+}
+
+/// The rewards of each monster the combo is applied to.
+pub fn simulate_combo(combo: &[ModifierId]) -> Vec<MonsterRewards> {
+    (1..=combo.len())
+        .map(|monster_count| {
+            MonsterRewards::new(
+                combo[..monster_count]
+                    .iter()
+                    .map(|modifier_id| &MODIFIERS.by_id[modifier_id]),
+            )
+        })
+        .collect()
+}
+
+/// Expected value of the rewards of each type, over all monsters the combo is applied to.
+pub fn get_expected_values(combo: &[ModifierId]) -> BTreeMap<Reward, f32> {
+    simulate_combo(combo)
+        .iter()
+        .flat_map(MonsterRewards::get_expected_values)
+        .fold(BTreeMap::new(), |mut expected_values, (reward, value)| {
+            *expected_values.entry(reward).or_default() += value;
+            expected_values
+        })
+}
+
+/// Estimates the value of the rewards of the counted types by sampling them `sample_count` times. The estimate
+/// is fully determined by the combo and the state of `rng`, and converges to the [`get_expected_values`].
+pub fn estimate_combo_value(
+    combo: &[ModifierId],
+    is_counted: impl Fn(&Reward) -> bool,
+    sample_count: usize,
+    rng: &mut Rng,
+) -> ValueEstimate {
+    if sample_count == 0 {
+        return ValueEstimate::default();
+    }
+
+    let monster_rewards = simulate_combo(combo);
+    let samples = (0..sample_count)
+        .map(|_| {
+            monster_rewards
+                .iter()
+                .map(|rewards| rewards.sample_value(&is_counted, rng))
+                .sum::<f64>()
+        })
+        .collect::<Vec<_>>();
+    let mean = samples.iter().sum::<f64>() / sample_count as f64;
+    let variance = samples
+        .iter()
+        .map(|sample| (sample - mean).powi(2))
+        .sum::<f64>()
+        / sample_count as f64;
+    ValueEstimate {
+        mean: mean as f32,
+        variance: variance as f32,
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::{BTreeSet, HashMap};
+use std::path::Path;
+
+use crate::{
+    collection,
+    data::{find_cycle, validate_modifiers, Modifier, ModifierId},
+    logic::MODIFIERS,
+};
+
+const REFERENCE_IMAGES_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/src/resources/reference_images"
+);
+
+/// One of the actual modifiers, which have reference images, with another recipe.
+fn get_modifier(modifier_id: ModifierId, recipe: BTreeSet<ModifierId>) -> Modifier {
+    Modifier {
+        recipe,
+        ..MODIFIERS.by_id[&modifier_id].clone()
+    }
+}
+
+fn validate(modifiers: &[Modifier]) -> Vec<String> {
+    validate_modifiers(modifiers, Path::new(REFERENCE_IMAGES_PATH))
+}
+
+#[test]
+fn validates_actual_modifiers() {
+    let modifiers = MODIFIERS.by_id.values().cloned().collect::<Vec<_>>();
+    assert_eq!(Vec::<String>::new(), validate(&modifiers));
+}
+
+#[test]
+fn finds_recipe_cycles() {
+    let recipe_1 = collection![2];
+    let recipe_2 = collection![0, 3];
+    let recipe_3 = collection![1];
+    let recipes: HashMap<_, _> = collection![1 => &recipe_1, 2 => &recipe_2, 3 => &recipe_3];
+    assert_eq!(
+        Some(vec![1, 2, 3, 1]),
+        find_cycle(1, &recipes, &mut Vec::new(), &mut BTreeSet::new())
+    );
+    // modifiers known to be acyclic are skipped
+    assert_eq!(
+        None,
+        find_cycle(1, &recipes, &mut Vec::new(), &mut collection![2])
+    );
+
+    let problems = validate(&[
+        get_modifier(0, collection![]),
+        get_modifier(1, collection![2]),
+        get_modifier(2, collection![0, 1]),
+    ]);
+    assert_eq!(1, problems.len());
+    assert!(problems[0].starts_with("recipe cycle: "));
+}
+
+#[test]
+fn rejects_duplicate_recipes() {
+    let problems = validate(&[
+        get_modifier(0, collection![]),
+        get_modifier(1, collection![]),
+        get_modifier(2, collection![0, 1]),
+        get_modifier(3, collection![0, 1]),
+    ]);
+    assert_eq!(
+        vec!["modifiers 2 and 3 have the same recipe {0, 1}".to_owned()],
+        problems
+    );
+}
+
+#[test]
+fn rejects_unknown_and_duplicate_ids() {
+    let problems = validate(&[
+        get_modifier(0, collection![]),
+        get_modifier(1, collection![0, 2]),
+    ]);
+    assert_eq!(1, problems.len());
+    assert!(problems[0].ends_with("references the unknown modifier 2"));
+
+    let problems = validate(&[
+        get_modifier(0, collection![]),
+        Modifier {
+            name: "Unnamed".into(),
+            ..get_modifier(0, collection![])
+        },
+    ]);
+    assert!(problems.contains(&"more than one modifier has the id 0".to_owned()));
+    // nor is there an image of it
+    assert!(problems
+        .iter()
+        .any(|problem| problem.starts_with("\"Unnamed\" has no reference image")));
+}
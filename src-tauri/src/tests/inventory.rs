@@ -0,0 +1,43 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    image::CellContent::{self, *},
+    inventory::{StashCell, StashInventory},
+};
+
+fn get_cell(content: CellContent, count: usize) -> StashCell {
+    StashCell { content, count }
+}
+
+#[test]
+fn counts_modifiers_by_cell() {
+    let stash_inventory = StashInventory::new([
+        get_cell(Modifier(3), 1),
+        get_cell(Modifier(3), 1),
+        get_cell(Modifier(5), 1),
+        get_cell(Empty, 1),
+        get_cell(Unknown, 1),
+    ]);
+    assert_eq!(
+        BTreeMap::from([(3, 2), (5, 1)]),
+        stash_inventory.get_modifier_counts()
+    );
+    assert_eq!(4, stash_inventory.get_taken_cell_count());
+    assert_eq!(1, stash_inventory.get_unknown_cell_count());
+}
+
+#[test]
+fn counts_stacked_modifiers() {
+    let stash_inventory = StashInventory::new([
+        get_cell(Modifier(3), 12),
+        get_cell(Modifier(3), 1),
+        get_cell(Modifier(5), 4),
+        get_cell(Empty, 1),
+    ]);
+    assert_eq!(
+        BTreeMap::from([(3, 13), (5, 4)]),
+        stash_inventory.get_modifier_counts()
+    );
+    // stacks take a single cell
+    assert_eq!(3, stash_inventory.get_taken_cell_count());
+}
@@ -0,0 +1,1114 @@
+mod data;
+mod inventory;
+mod prices;
+mod properties;
+mod rewards;
+mod synthetic_captures;
+mod templates;
+
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::sync::atomic::Ordering;
+
+use crate::{
+    cache::{Cache, Fingerprints, MAX_CACHED_COMBOS},
+    catalog::{export_combos, import_combos, ShareError, ShareFormat},
+    collection,
+    image::CellContent,
+    logic::{
+        get_cache_key, get_combo_reward_value, get_reserved_modifier_ids, get_settings_cache_key,
+        suggest_combo, suggest_combo_cached, ComboRuns, Hint, LabeledCombo, ModifierId, Reward,
+        RiskPreference, SolverMode, SolverSettings, Suggestion, UserSettings, DEFAULT_COMBO_WEIGHT,
+        STASH_CAPACITY,
+    },
+    utils::{stable_hash, DiscSynchronized, StableHasher},
+};
+
+/// Test stashes hold a single modifier per cell, rather than stacks.
+fn get_stash_cell_count(stash: &BTreeMap<ModifierId, usize>) -> usize {
+    stash.values().sum()
+}
+
+fn get_suggested_combo(
+    combos: Vec<Vec<ModifierId>>,
+    forbidden_modifier_ids: BTreeSet<ModifierId>,
+    stash: BTreeMap<ModifierId, usize>,
+    queue: Vec<ModifierId>,
+) -> Option<Vec<ModifierId>> {
+    suggest_combo(
+        &UserSettings {
+            combo_catalog: combos
+                .iter()
+                .enumerate()
+                .map(|(index, combo)| {
+                    LabeledCombo::new(index as u64, format!("Combo #{}", index), combo.clone())
+                })
+                .collect_vec(),
+            combo_roster: (0..combos.len() as u64).collect_vec(),
+            forbidden_modifier_ids,
+            hotkey: "".into(),
+            show_tiers: false,
+            solver_settings: SolverSettings::default(),
+            manual_layout: None,
+        },
+        &stash,
+        get_stash_cell_count(&stash),
+        &queue,
+    )
+    .map(|suggestion| suggestion.combo)
+}
+
+#[test]
+fn empty_stash() {
+    assert_eq!(
+        None,
+        get_suggested_combo(
+            collection![vec![0, 1, 2, 3]],
+            collection![],
+            collection![],
+            collection![]
+        )
+    );
+}
+
+#[test]
+fn full_queue() {
+    assert_eq!(
+        None,
+        get_suggested_combo(
+            collection![vec![0, 1, 2, 3]],
+            collection![],
+            collection![0 => 1, 1 => 1, 2 => 1, 3 => 1],
+            collection![7, 8, 9, 10]
+        )
+    );
+}
+
+#[test]
+fn high_priority_combo() {
+    assert_eq!(
+        Some(vec![0, 1, 2, 3]),
+        get_suggested_combo(
+            collection![vec![0, 1, 2, 3], vec![4, 5, 6, 7]],
+            collection![],
+            collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 4 => 1, 5 => 1, 6 => 1, 7 => 1],
+            collection![]
+        )
+    );
+}
+
+#[test]
+fn complete_queue() {
+    assert_eq!(
+        Some(vec![0, 1, 2, 3]),
+        get_suggested_combo(
+            collection![vec![0, 1, 2, 3], vec![4, 5, 6, 7]],
+            collection![],
+            collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 4 => 1, 5 => 1, 6 => 1, 7 => 1],
+            collection![0, 1, 2]
+        )
+    );
+}
+
+#[test]
+fn only_fillers() {
+    assert_eq!(
+        None,
+        get_suggested_combo(
+            collection![vec![0, 1, 2, 3]],
+            collection![],
+            collection![4 => 1, 5 => 1, 6 => 1, 7 => 1],
+            collection![]
+        )
+    );
+}
+
+#[test]
+fn recipe_with_fillers() {
+    let combo = get_suggested_combo(
+        collection![vec![0, 1, 2, 29]],
+        collection![],
+        collection![5 => 1, 20 => 1, 51 => 1, 52 => 1],
+        collection![],
+    );
+    assert!(combo.is_some());
+    let combo = combo.unwrap();
+    assert!(combo.iter().collect::<HashSet<_>>().contains(&5));
+    assert!(combo.iter().collect::<HashSet<_>>().contains(&20));
+}
+
+#[test]
+fn does_not_mess_up_recipe() {
+    let combo = get_suggested_combo(
+        collection![vec![38, 60, 57, 46], vec![37, 38, 31, 4]],
+        collection![54, 55, 56, 59, 61, 62],
+        collection![19 => 1, 34 => 1, 20 => 1, 9 => 1, 7 => 1],
+        collection![],
+    );
+    assert!(combo.is_some());
+    let combo = combo.unwrap();
+    assert_eq!(combo, vec![19, 34, 20, 9]);
+
+    let combo = get_suggested_combo(
+        collection![vec![38, 60, 57, 46], vec![37, 38, 31, 4]],
+        collection![54, 55, 56, 59, 61, 62],
+        collection![19 => 1, 34 => 1, 20 => 1, 9 => 1, 7 => 1],
+        collection![19, 34, 20],
+    );
+    assert!(combo.is_some());
+    let combo = combo.unwrap();
+    assert_eq!(combo, vec![19, 34, 20, 9]);
+}
+
+#[test]
+fn stash_full_four_filler() {
+    let combo = get_suggested_combo(
+        collection![],
+        collection![],
+        collection![0 => 15, 1 => 15, 2 => 15, 3 => 14],
+        collection![],
+    );
+    assert!(combo.is_none());
+
+    let combo = get_suggested_combo(
+        collection![],
+        collection![],
+        collection![0 => 15, 1 => 15, 2 => 15, 3 => 15],
+        collection![],
+    );
+    assert!(combo.is_some());
+}
+
+#[test]
+fn stash_full_no_forbidden_fillers() {
+    let combo = get_suggested_combo(
+        collection![],
+        collection![0, 1, 2, 3],
+        collection![0 => 15, 1 => 15, 2 => 15, 3 => 15],
+        collection![],
+    );
+    assert!(combo.is_none());
+}
+
+#[test]
+fn stash_full_counts_cells() {
+    let user_settings = UserSettings {
+        combo_catalog: vec![],
+        combo_roster: vec![],
+        forbidden_modifier_ids: collection![],
+        hotkey: "".into(),
+        show_tiers: false,
+        solver_settings: SolverSettings::default(),
+        manual_layout: None,
+    };
+    let stash = collection![0 => 15, 1 => 15, 2 => 15, 3 => 15];
+    // the same modifiers stacked in a few cells leave plenty of room
+    assert_eq!(None, suggest_combo(&user_settings, &stash, 4, &[]));
+    assert!(suggest_combo(&user_settings, &stash, 60, &[]).is_some());
+}
+
+#[test]
+fn duplicate_modifier_combo() {
+    assert_eq!(
+        None,
+        get_suggested_combo(
+            collection![vec![0, 0, 1, 2]],
+            collection![],
+            collection![0 => 1, 1 => 1, 2 => 1],
+            collection![]
+        )
+    );
+    assert_eq!(
+        Some(vec![0, 0, 1, 2]),
+        get_suggested_combo(
+            collection![vec![0, 0, 1, 2]],
+            collection![],
+            collection![0 => 2, 1 => 1, 2 => 1],
+            collection![]
+        )
+    );
+    assert_eq!(
+        Some(vec![0, 0, 1, 2]),
+        get_suggested_combo(
+            collection![vec![0, 0, 1, 2]],
+            collection![],
+            collection![0 => 1, 1 => 1, 2 => 1],
+            collection![0]
+        )
+    );
+}
+
+#[test]
+fn reuses_last_suggested_combo_only_if_affordable() {
+    let mut cache = Cache::create_new();
+    let user_settings = UserSettings {
+        combo_catalog: vec![LabeledCombo::new(0, "".into(), vec![0, 0, 1, 2])],
+        combo_roster: vec![0],
+        forbidden_modifier_ids: collection![],
+        hotkey: "".into(),
+        show_tiers: false,
+        solver_settings: SolverSettings::default(),
+        manual_layout: None,
+    };
+    assert_eq!(
+        Some(vec![0, 0, 1, 2]),
+        suggest_combo_cached(
+            &mut cache,
+            &user_settings,
+            &collection![0 => 2, 1 => 1, 2 => 1],
+            4,
+            &[]
+        )
+        .map(|suggestion| suggestion.combo)
+    );
+    assert_eq!(
+        None,
+        suggest_combo_cached(
+            &mut cache,
+            &user_settings,
+            &collection![0 => 1, 1 => 1, 2 => 1],
+            3,
+            &[]
+        )
+        .map(|suggestion| suggestion.combo)
+    );
+    assert_eq!(
+        Some(vec![0, 0, 1, 2]),
+        suggest_combo_cached(
+            &mut cache,
+            &user_settings,
+            &collection![0 => 1, 1 => 1, 2 => 1],
+            3,
+            &[0]
+        )
+        .map(|suggestion| suggestion.combo)
+    );
+}
+
+fn get_suggestion(
+    combo: Vec<ModifierId>,
+    stash: BTreeMap<ModifierId, usize>,
+    queue: Vec<ModifierId>,
+) -> Option<Suggestion> {
+    suggest_combo(
+        &UserSettings {
+            combo_catalog: vec![LabeledCombo::new(0, "Combo #0".into(), combo)],
+            combo_roster: vec![0],
+            forbidden_modifier_ids: collection![],
+            hotkey: "".into(),
+            show_tiers: false,
+            solver_settings: SolverSettings::default(),
+            manual_layout: None,
+        },
+        &stash,
+        get_stash_cell_count(&stash),
+        &queue,
+    )
+}
+
+#[test]
+fn builds_missing_intermediate() {
+    let suggestion = get_suggestion(
+        vec![39, 0, 1, 3],
+        collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 4 => 1, 5 => 1, 6 => 1],
+        vec![],
+    )
+    .unwrap();
+    assert_eq!(
+        vec![2, 4, 5, 6],
+        suggestion.combo.iter().copied().sorted().collect_vec()
+    );
+    assert_eq!(
+        vec![Hint::BuildsToward {
+            modifier_id: 39,
+            label: "Combo #0".into()
+        }],
+        suggestion.hints
+    );
+
+    let suggestion = get_suggestion(
+        vec![39, 0, 1, 3],
+        collection![0 => 1, 1 => 1, 3 => 1, 4 => 1, 5 => 1, 6 => 1],
+        vec![2],
+    )
+    .unwrap();
+    assert_eq!(2, suggestion.combo[0]);
+    assert_eq!(1, suggestion.hints.len());
+}
+
+#[test]
+fn builds_nested_intermediate() {
+    let suggestion = get_suggestion(
+        vec![51, 0, 1, 2],
+        collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 6 => 1, 7 => 1, 16 => 1, 17 => 1, 20 => 1],
+        vec![],
+    )
+    .unwrap();
+    assert_eq!(
+        vec![6, 7, 16, 20],
+        suggestion.combo.iter().copied().sorted().collect_vec()
+    );
+    assert_eq!(
+        vec![Hint::BuildsToward {
+            modifier_id: 32,
+            label: "Combo #0".into()
+        }],
+        suggestion.hints
+    );
+}
+
+#[test]
+fn does_not_build_unreachable_intermediate() {
+    let suggestion = get_suggestion(
+        vec![39, 0, 1, 3],
+        collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 5 => 1, 6 => 1],
+        vec![],
+    );
+    assert!(suggestion.map_or(true, |suggestion| suggestion.hints.is_empty()));
+}
+
+fn get_roster_user_settings(combo_catalog: Vec<LabeledCombo>) -> UserSettings {
+    UserSettings {
+        combo_roster: combo_catalog.iter().map(|combo| combo.id).collect_vec(),
+        combo_catalog,
+        forbidden_modifier_ids: collection![],
+        hotkey: "".into(),
+        show_tiers: false,
+        solver_settings: SolverSettings::default(),
+        manual_layout: None,
+    }
+}
+
+#[test]
+fn weighted_combo_takes_priority() {
+    let stash = collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 4 => 1, 5 => 1, 6 => 1, 7 => 1];
+    let mut user_settings = get_roster_user_settings(vec![
+        LabeledCombo::new(0, "".into(), vec![0, 1, 2, 3]),
+        LabeledCombo::new(1, "".into(), vec![4, 5, 6, 7]),
+    ]);
+    assert_eq!(
+        Some(vec![0, 1, 2, 3]),
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+            .map(|suggestion| suggestion.combo)
+    );
+
+    // the second combo ties with the first one at twice the weight, and wins above that
+    user_settings.combo_catalog[1].weight = 2 * DEFAULT_COMBO_WEIGHT;
+    assert_eq!(
+        Some(vec![0, 1, 2, 3]),
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+            .map(|suggestion| suggestion.combo)
+    );
+    user_settings.combo_catalog[1].weight = 3 * DEFAULT_COMBO_WEIGHT;
+    assert_eq!(
+        Some(vec![4, 5, 6, 7]),
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+            .map(|suggestion| suggestion.combo)
+    );
+}
+
+#[test]
+fn skips_disabled_and_exhausted_combos() {
+    let stash = collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 4 => 1, 5 => 1, 6 => 1, 7 => 1];
+    let mut user_settings = get_roster_user_settings(vec![
+        LabeledCombo::new(0, "".into(), vec![0, 1, 2, 3]),
+        LabeledCombo::new(1, "".into(), vec![4, 5, 6, 7]),
+    ]);
+    user_settings.combo_catalog[0].enabled = false;
+    assert_eq!(
+        Some(vec![4, 5, 6, 7]),
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+            .map(|suggestion| suggestion.combo)
+    );
+
+    user_settings.combo_catalog[0].enabled = true;
+    user_settings.combo_catalog[0].max_runs = Some(2);
+    let mut combo_runs = ComboRuns::default();
+    for _ in 0..2 {
+        let pending_user_settings = combo_runs.get_pending_user_settings(&user_settings);
+        let suggestion = suggest_combo(
+            &pending_user_settings,
+            &stash,
+            get_stash_cell_count(&stash),
+            &[0, 1, 2],
+        )
+        .unwrap();
+        assert_eq!(vec![0, 1, 2, 3], suggestion.combo);
+        combo_runs.observe_suggestion(&pending_user_settings, &[0, 1, 2], &suggestion);
+        assert_eq!(
+            None,
+            combo_runs.observe_queue(&user_settings, &[0, 1, 2, 3])
+        );
+        assert_eq!(Some(0), combo_runs.observe_queue(&user_settings, &[]));
+    }
+
+    let pending_user_settings = combo_runs.get_pending_user_settings(&user_settings);
+    assert_eq!(vec![1], pending_user_settings.combo_roster);
+    assert_eq!(
+        Some(vec![4, 5, 6, 7]),
+        suggest_combo(
+            &pending_user_settings,
+            &stash,
+            get_stash_cell_count(&stash),
+            &[]
+        )
+        .map(|suggestion| suggestion.combo)
+    );
+
+    combo_runs.reset(0);
+    assert_eq!(0, combo_runs.get(0));
+    assert_eq!(
+        user_settings,
+        combo_runs.get_pending_user_settings(&user_settings)
+    );
+}
+
+#[test]
+fn counts_combo_runs_once_queue_is_emptied() {
+    let stash = collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 4 => 1, 5 => 1, 6 => 1, 7 => 1];
+    let user_settings = get_roster_user_settings(vec![
+        LabeledCombo::new(0, "".into(), vec![0, 1, 2, 3]),
+        LabeledCombo::new(1, "".into(), vec![4, 5, 6, 7]),
+    ]);
+    let mut combo_runs = ComboRuns::default();
+
+    // suggesting a combo for an empty queue, even repeatedly, is no run
+    for _ in 0..2 {
+        assert_eq!(None, combo_runs.observe_queue(&user_settings, &[]));
+        let suggestion =
+            suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[]).unwrap();
+        combo_runs.observe_suggestion(&user_settings, &[], &suggestion);
+    }
+    assert_eq!(None, combo_runs.pending_combo_id);
+
+    let suggestion = suggest_combo(
+        &user_settings,
+        &stash,
+        get_stash_cell_count(&stash),
+        &[0, 1, 2],
+    )
+    .unwrap();
+    combo_runs.observe_suggestion(&user_settings, &[0, 1, 2], &suggestion);
+    assert_eq!(Some(0), combo_runs.pending_combo_id);
+
+    // another modifier was queued instead of the suggested one
+    assert_eq!(
+        None,
+        combo_runs.observe_queue(&user_settings, &[0, 1, 2, 7])
+    );
+    assert_eq!(None, combo_runs.observe_queue(&user_settings, &[]));
+    assert_eq!(0, combo_runs.get(0));
+
+    // a combo queued without being suggested is counted as well, but only once
+    for _ in 0..2 {
+        assert_eq!(
+            None,
+            combo_runs.observe_queue(&user_settings, &[4, 5, 6, 7])
+        );
+    }
+    assert_eq!(Some(1), combo_runs.observe_queue(&user_settings, &[4]));
+    assert_eq!(None, combo_runs.observe_queue(&user_settings, &[]));
+    assert_eq!(1, combo_runs.get(1));
+}
+
+#[test]
+fn counts_combo_runs_with_shorter_queue() {
+    let stash = collection![0 => 1, 1 => 1, 2 => 1, 3 => 1];
+    let mut user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![0, 1, 2])]);
+    user_settings.solver_settings.queue_length = 3;
+    user_settings.solver_settings.max_effective_fillers = 2;
+    user_settings.combo_catalog[0].max_runs = Some(1);
+    let mut combo_runs = ComboRuns::default();
+
+    let suggestion = suggest_combo(
+        &user_settings,
+        &stash,
+        get_stash_cell_count(&stash),
+        &[0, 1],
+    )
+    .unwrap();
+    assert_eq!(vec![0, 1, 2], suggestion.combo);
+    combo_runs.observe_suggestion(&user_settings, &[0, 1], &suggestion);
+    assert_eq!(Some(0), combo_runs.pending_combo_id);
+    assert_eq!(
+        None,
+        suggest_combo(
+            &user_settings,
+            &stash,
+            get_stash_cell_count(&stash),
+            &[0, 1, 2]
+        )
+    );
+
+    assert_eq!(None, combo_runs.observe_queue(&user_settings, &[0, 1, 2]));
+    assert_eq!(Some(0), combo_runs.observe_queue(&user_settings, &[]));
+    assert!(combo_runs
+        .get_pending_user_settings(&user_settings)
+        .combo_roster
+        .is_empty());
+}
+
+#[test]
+fn honors_filler_tolerance() {
+    let stash = collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 4 => 1, 5 => 1, 6 => 1];
+    let mut user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![39, 0, 1, 3])]);
+    assert!(suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[]).is_some());
+
+    // building Frost Strider takes two fillers
+    user_settings.combo_catalog[0].filler_tolerance = Some(1);
+    assert_eq!(
+        None,
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+    );
+
+    // the solver settings apply to combos without a tolerance of their own
+    user_settings.combo_catalog[0].filler_tolerance = None;
+    user_settings.solver_settings.max_effective_fillers = 1;
+    assert_eq!(
+        None,
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+    );
+}
+
+#[test]
+fn validates_solver_settings() {
+    assert!(SolverSettings::default().validate().is_ok());
+    for solver_settings in [
+        SolverSettings {
+            queue_length: 0,
+            ..SolverSettings::default()
+        },
+        SolverSettings {
+            queue_length: 5,
+            ..SolverSettings::default()
+        },
+        SolverSettings {
+            stash_threshold: 65,
+            ..SolverSettings::default()
+        },
+        SolverSettings {
+            tier1_filler_min_count: 0,
+            ..SolverSettings::default()
+        },
+        SolverSettings {
+            queue_length: 3,
+            max_effective_fillers: 4,
+            ..SolverSettings::default()
+        },
+    ] {
+        assert!(solver_settings.validate().is_err(), "{:?}", solver_settings);
+    }
+}
+
+#[test]
+fn solver_settings_tune_stash_clearing() {
+    let stash = collection![0 => 15, 1 => 15, 2 => 15, 3 => 14];
+    let mut user_settings = get_roster_user_settings(vec![]);
+    assert_eq!(
+        None,
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+    );
+
+    user_settings.solver_settings.stash_threshold = 59;
+    assert!(suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[]).is_some());
+
+    // shorter custom combos
+    user_settings.solver_settings.queue_length = 3;
+    assert_eq!(
+        Some(3),
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+            .map(|suggestion| suggestion.combo.len())
+    );
+}
+
+#[test]
+fn old_user_settings_get_defaults() {
+    let user_settings = serde_json::from_str::<UserSettings>(
+        r#"{
+            "comboCatalog": [{ "id": 0, "label": "", "combo": [0, 1, 2, 3] }],
+            "comboRoster": [0],
+            "forbiddenModifierIds": [],
+            "hotkey": "shift + x",
+            "showTiers": false
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(SolverSettings::default(), user_settings.solver_settings);
+    assert_eq!(None, user_settings.manual_layout);
+    assert_eq!(
+        LabeledCombo::new(0, "".into(), vec![0, 1, 2, 3]),
+        user_settings.combo_catalog[0]
+    );
+}
+
+#[test]
+fn reserves_modifiers_for_roster() {
+    let user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![39, 0, 1, 3])]);
+    assert_eq!(
+        vec![(0, 1), (2, 1), (4, 1)],
+        get_reserved_modifier_ids(&user_settings, &collection![0 => 2, 2 => 1, 4 => 1, 5 => 1])
+            .into_iter()
+            .collect_vec()
+    );
+    assert_eq!(
+        vec![(0, 1), (39, 1)],
+        get_reserved_modifier_ids(
+            &user_settings,
+            &collection![0 => 2, 2 => 1, 4 => 1, 39 => 1]
+        )
+        .into_iter()
+        .collect_vec()
+    );
+}
+
+#[test]
+fn stash_full_keeps_reserved_modifiers() {
+    let user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![39, 0, 1, 3])]);
+    let suggestion = suggest_combo(
+        &user_settings,
+        &collection![2 => 3, 5 => 15, 6 => 15, 7 => 15, 8 => 12],
+        60,
+        &[],
+    )
+    .unwrap();
+    assert_eq!(
+        vec![5, 6, 7, 8],
+        suggestion.combo.iter().copied().sorted().collect_vec()
+    );
+    assert_eq!(
+        vec![Hint::Reserved {
+            modifier_id: 2,
+            count: 1
+        }],
+        suggestion.hints
+    );
+
+    // with enough surplus copies, the modifier may be used as a filler again
+    let suggestion = suggest_combo(
+        &user_settings,
+        &collection![2 => 4, 5 => 15, 6 => 15, 7 => 15, 8 => 11],
+        60,
+        &[],
+    )
+    .unwrap();
+    assert!(suggestion.combo.contains(&2));
+    assert!(suggestion.hints.is_empty());
+}
+
+#[test]
+fn clears_full_stash() {
+    let user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![39, 0, 1, 3])]);
+    let mut stash = collection![2 => 1, 4 => 1, 6 => 30, 7 => 20, 8 => 10, 16 => 1, 20 => 1];
+    // Rejuvenating (16 and 20) would return to the stash, freeing one cell less
+    let suggestion =
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[]).unwrap();
+    assert_eq!(
+        vec![6, 7, 8],
+        suggestion
+            .combo
+            .iter()
+            .copied()
+            .sorted()
+            .take(3)
+            .collect_vec()
+    );
+    assert_eq!(
+        vec![Hint::ClearingStash { freed_cells: 4 }],
+        suggestion.hints
+    );
+
+    // queued modifiers make recipes too
+    let suggestion =
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[16]).unwrap();
+    assert_eq!(
+        vec![6, 7, 8, 16],
+        suggestion.combo.iter().copied().sorted().collect_vec()
+    );
+    assert_eq!(16, suggestion.combo[0]);
+    assert_eq!(
+        vec![Hint::ClearingStash { freed_cells: 3 }],
+        suggestion.hints
+    );
+
+    // one cell left, business as usual
+    *stash.get_mut(&8).unwrap() -= 1;
+    assert!(
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[]).map_or(
+            true,
+            |suggestion| suggestion
+                .hints
+                .iter()
+                .all(|hint| !matches!(hint, Hint::ClearingStash { .. }))
+        )
+    );
+}
+
+#[test]
+fn clears_full_stash_of_stacks() {
+    let user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![39, 0, 1, 3])]);
+    let stash =
+        collection![2 => 1, 4 => 1, 6 => 40, 7 => 20, 8 => 10, 10 => 1, 13 => 1, 16 => 1, 20 => 1];
+    // only the last modifier of a stack frees its cell, and Rejuvenating would take one again
+    let suggestion = suggest_combo(&user_settings, &stash, STASH_CAPACITY, &[]).unwrap();
+    assert_eq!(
+        vec![Hint::ClearingStash { freed_cells: 3 }],
+        suggestion.hints
+    );
+
+    // nothing to free
+    let stash = collection![2 => 1, 4 => 1, 6 => 40, 7 => 20, 8 => 10, 10 => 2, 13 => 2];
+    assert!(
+        suggest_combo(&user_settings, &stash, STASH_CAPACITY, &[]).map_or(true, |suggestion| {
+            suggestion
+                .hints
+                .iter()
+                .all(|hint| !matches!(hint, Hint::ClearingStash { .. }))
+        })
+    );
+}
+
+#[test]
+fn targets_rewards() {
+    let mut user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![0, 1, 2, 3])]);
+    user_settings.solver_settings.mode = SolverMode::TargetRewards(collection![Reward::Scarab]);
+    let stash = collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 16 => 2, 46 => 1, 55 => 1, 56 => 1];
+    let combo = suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+        .unwrap()
+        .combo;
+    assert_eq!(4, combo.len());
+    assert!(combo.contains(&46) && combo.contains(&55));
+    // converting to divination cards would lose the scarabs
+    assert!(!combo.contains(&56));
+    assert!(get_combo_reward_value(&combo, &collection![Reward::Scarab]) > 0.0);
+
+    // nothing drops scarabs
+    assert_eq!(
+        None,
+        suggest_combo(
+            &user_settings,
+            &collection![0 => 1, 1 => 1, 2 => 1, 3 => 1],
+            4,
+            &[]
+        )
+    );
+
+    user_settings.solver_settings.mode =
+        SolverMode::TargetRewards(collection![Reward::DivinationCard]);
+    assert!(
+        suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+            .unwrap()
+            .combo
+            .contains(&56)
+    );
+    assert!(SolverSettings {
+        mode: SolverMode::TargetRewards(collection![]),
+        ..SolverSettings::default()
+    }
+    .validate()
+    .is_err());
+}
+
+#[test]
+fn reuses_last_suggested_combo_only_with_same_settings() {
+    let mut cache = Cache::create_new();
+    let mut user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![0, 1, 2, 3])]);
+    let stash = collection![0 => 1, 1 => 1, 2 => 1, 3 => 1, 16 => 2, 46 => 1, 55 => 1, 56 => 1];
+    let stash_cell_count = get_stash_cell_count(&stash);
+    assert_eq!(
+        vec![0, 1, 2, 3],
+        suggest_combo_cached(&mut cache, &user_settings, &stash, stash_cell_count, &[])
+            .unwrap()
+            .combo
+    );
+
+    // the roster combo is still affordable, but no longer what the solver is after
+    user_settings.solver_settings.mode = SolverMode::TargetRewards(collection![Reward::Scarab]);
+    let combo = suggest_combo_cached(&mut cache, &user_settings, &stash, stash_cell_count, &[])
+        .unwrap()
+        .combo;
+    assert!(combo.contains(&46) && combo.contains(&55));
+
+    user_settings.solver_settings.mode = SolverMode::Roster;
+    assert_eq!(
+        vec![0, 1, 2, 3],
+        suggest_combo_cached(&mut cache, &user_settings, &stash, stash_cell_count, &[])
+            .unwrap()
+            .combo
+    );
+}
+
+#[test]
+fn reuses_last_suggested_combo_only_with_same_stash_pressure() {
+    let mut cache = Cache::create_new();
+    let user_settings =
+        get_roster_user_settings(vec![LabeledCombo::new(0, "".into(), vec![39, 0, 1, 3])]);
+    let stash = collection![2 => 1, 4 => 1, 6 => 30, 7 => 20, 8 => 10, 16 => 1, 20 => 1];
+    let suggestion =
+        suggest_combo_cached(&mut cache, &user_settings, &stash, STASH_CAPACITY - 1, &[]).unwrap();
+    assert!(!matches!(
+        suggestion.hints[..],
+        [Hint::ClearingStash { .. }]
+    ));
+
+    // the same stash fills up, e.g. once the last cell is recognized
+    let suggestion =
+        suggest_combo_cached(&mut cache, &user_settings, &stash, STASH_CAPACITY, &[]).unwrap();
+    assert!(matches!(suggestion.hints[..], [Hint::ClearingStash { .. }]));
+}
+
+#[test]
+fn honors_risk_preference() {
+    let mut user_settings = get_roster_user_settings(vec![]);
+    user_settings.solver_settings.queue_length = 2;
+    user_settings.solver_settings.mode = SolverMode::TargetRewards(collection![Reward::Unique]);
+    // both combos are worth 3 uniques on average, but Mirror Image rerolls 2 of them while Solaris-Touched
+    // adds a third one
+    let stash = collection![46 => 1, 55 => 1, 60 => 1];
+
+    user_settings.solver_settings.risk_preference = RiskPreference::Safe;
+    let combo = suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+        .unwrap()
+        .combo;
+    assert!(combo.contains(&46) && combo.contains(&60));
+
+    user_settings.solver_settings.risk_preference = RiskPreference::HighVariance;
+    let combo = suggest_combo(&user_settings, &stash, get_stash_cell_count(&stash), &[])
+        .unwrap()
+        .combo;
+    assert!(combo.contains(&55) && combo.contains(&60));
+}
+
+#[test]
+fn export_import_round_trip() {
+    for format in [ShareFormat::Compact, ShareFormat::Json] {
+        let mut user_settings = UserSettings::create_new();
+        user_settings.combo_catalog[0].label = "Tricky: label, with | separators %".into();
+        let data = export_combos(&user_settings, &[0, 1], format).unwrap();
+        let combo_ids = import_combos(&mut user_settings, &data).unwrap();
+        assert_eq!(vec![2, 3], combo_ids);
+        assert_eq!(
+            user_settings.combo_catalog[0].label,
+            user_settings.combo_catalog[2].label
+        );
+        assert_eq!(
+            user_settings.combo_catalog[0].combo,
+            user_settings.combo_catalog[2].combo
+        );
+        assert_eq!(
+            user_settings.combo_catalog[1].combo,
+            user_settings.combo_catalog[3].combo
+        );
+    }
+}
+
+#[test]
+fn import_invalid_combos() {
+    let mut user_settings = UserSettings::create_new();
+    assert_eq!(
+        Err(ShareError::UnknownModifier("Foo".into())),
+        import_combos(&mut user_settings, "archbroski:x:Toxic,Foo,Hasted,Deadeye")
+    );
+    assert_eq!(
+        Err(ShareError::InvalidCombo("x".into())),
+        import_combos(
+            &mut user_settings,
+            "archbroski:x:Toxic,Toxic,Hasted,Deadeye"
+        )
+    );
+    assert_eq!(
+        Err(ShareError::InvalidCombo("x".into())),
+        import_combos(&mut user_settings, "archbroski:x:Toxic,Hasted")
+    );
+    assert_eq!(2, user_settings.combo_catalog.len());
+}
+
+#[test]
+fn export_unknown_modifier_ids() {
+    let mut user_settings = UserSettings::create_new();
+    user_settings.combo_catalog[0].combo[1] = ModifierId::MAX;
+    assert_eq!(
+        Err(ShareError::UnknownModifierId(ModifierId::MAX)),
+        export_combos(&user_settings, &[0], ShareFormat::Compact)
+    );
+    assert!(export_combos(&user_settings, &[1], ShareFormat::Compact).is_ok());
+}
+
+#[test]
+fn evicts_least_recently_used_combos() {
+    let mut cache = Cache::create_new();
+    for key in 0..(MAX_CACHED_COMBOS as u64) {
+        cache.get_or_insert_combo(key, || Some(Suggestion::new(vec![0, 1, 2, 3])));
+    }
+
+    // the oldest entry is used again, so the next oldest ones are evicted instead
+    cache.modified = false;
+    assert_eq!(
+        Some(Suggestion::new(vec![0, 1, 2, 3])),
+        cache.get_or_insert_combo(0, || None)
+    );
+    // hits alone are persisted on exit
+    assert!(!cache.modified);
+    assert!(cache.recency_modified.load(Ordering::Relaxed));
+    assert_eq!(1, cache.get_stats().combo_hits);
+    for key in (MAX_CACHED_COMBOS as u64)..(MAX_CACHED_COMBOS as u64 + 10) {
+        cache.get_or_insert_combo(key, || Some(Suggestion::new(vec![0, 1, 2, 3])));
+    }
+
+    cache.evict();
+    assert_eq!(MAX_CACHED_COMBOS, cache.suggested_combos.len());
+    assert!(cache.suggested_combos.contains_key(&0));
+    assert!((1..11).all(|key| !cache.suggested_combos.contains_key(&key)));
+    assert!(cache
+        .suggested_combos
+        .contains_key(&(MAX_CACHED_COMBOS as u64 + 9)));
+}
+
+#[test]
+fn invalidates_only_stale_sections() {
+    let mut cache = Cache::create_new();
+    cache.layout = Some(HashMap::new());
+    cache.get_or_insert_image(0, 0, |_| true, || CellContent::Modifier(0));
+    cache.get_or_insert_combo(0, || Some(Suggestion::new(vec![0, 1, 2, 3])));
+
+    cache.invalidate_stale(Fingerprints {
+        solver: cache.fingerprints.solver.wrapping_add(1),
+        ..cache.fingerprints
+    });
+    assert!(cache.layout.is_some());
+    assert_eq!(1, cache.images.len());
+    assert!(cache.suggested_combos.is_empty());
+
+    cache.get_or_insert_combo(0, || Some(Suggestion::new(vec![0, 1, 2, 3])));
+    cache.invalidate_stale(Fingerprints {
+        templates: cache.fingerprints.templates.wrapping_add(1),
+        ..cache.fingerprints
+    });
+    assert!(cache.layout.is_none());
+    assert!(cache.images.is_empty());
+    assert_eq!(1, cache.suggested_combos.len());
+}
+
+#[test]
+fn matches_similar_image_hashes() {
+    let cache = Cache::create_new();
+    assert_eq!(
+        CellContent::Modifier(1),
+        cache.get_or_insert_image(0b1011, 2, |_| true, || CellContent::Modifier(1))
+    );
+    assert_eq!(
+        CellContent::Modifier(1),
+        cache.get_or_insert_image(0b0010, 2, |_| true, || CellContent::Modifier(2))
+    );
+    assert_eq!(
+        CellContent::Modifier(3),
+        cache.get_or_insert_image(0b0100, 2, |_| true, || CellContent::Modifier(3))
+    );
+    assert_eq!(
+        CellContent::Empty,
+        cache.get_or_insert_image(0b1111_0000, 2, |_| true, || CellContent::Empty)
+    );
+    assert_eq!(3, cache.images.len());
+    assert_eq!(1, cache.get_stats().image_hits);
+}
+
+#[test]
+fn confirms_similar_image_hashes() {
+    let cache = Cache::create_new();
+    cache.get_or_insert_image(0b1011, 2, |_| true, || CellContent::Modifier(1));
+    // a similar hash of another modifier
+    assert_eq!(
+        CellContent::Modifier(2),
+        cache.get_or_insert_image(
+            0b0011,
+            2,
+            |content| content == CellContent::Modifier(2),
+            || CellContent::Modifier(2)
+        )
+    );
+    // identical hashes need no confirmation
+    assert_eq!(
+        CellContent::Modifier(1),
+        cache.get_or_insert_image(0b1011, 2, |_| false, || CellContent::Modifier(3))
+    );
+    assert_eq!(2, cache.images.len());
+    assert_eq!(1, cache.get_stats().image_hits);
+}
+
+#[test]
+fn cache_key_ignores_unrelated_settings() {
+    let stash = collection![0 => 1, 1 => 2];
+    let queue = vec![4];
+    let user_settings = UserSettings::create_new();
+    let cache_key = get_cache_key(&user_settings, &stash, 3, &queue);
+    assert_ne!(cache_key, get_cache_key(&user_settings, &stash, 2, &queue));
+
+    let mut user_settings_ = user_settings.clone();
+    user_settings_.hotkey = "ctrl + F1".into();
+    user_settings_.show_tiers = true;
+    user_settings_
+        .combo_catalog
+        .push(LabeledCombo::new(2, "".into(), vec![0, 1, 2, 3]));
+    assert_eq!(cache_key, get_cache_key(&user_settings_, &stash, 3, &queue));
+
+    let mut user_settings_ = user_settings.clone();
+    user_settings_.combo_roster.reverse();
+    assert_ne!(cache_key, get_cache_key(&user_settings_, &stash, 3, &queue));
+
+    // hints refer to roster combos by label
+    let mut user_settings_ = user_settings.clone();
+    user_settings_.combo_catalog[0].label = "Renamed".into();
+    assert_ne!(cache_key, get_cache_key(&user_settings_, &stash, 3, &queue));
+
+    let mut user_settings_ = user_settings.clone();
+    user_settings_.combo_catalog[0].weight = 2 * DEFAULT_COMBO_WEIGHT;
+    assert_ne!(cache_key, get_cache_key(&user_settings_, &stash, 3, &queue));
+
+    let mut user_settings_ = user_settings.clone();
+    user_settings_.combo_catalog[1].enabled = false;
+    assert_ne!(cache_key, get_cache_key(&user_settings_, &stash, 3, &queue));
+    assert_ne!(
+        cache_key,
+        get_cache_key(&user_settings, &collection![0 => 2, 1 => 2], 3, &queue)
+    );
+}
+
+#[test]
+fn stable_hashes_are_pinned() {
+    // persisted keys and fingerprints must not change with the Rust version; update these values only along
+    // with a deliberate change of the hashed inputs
+    let mut hasher = StableHasher::default();
+    hasher.write_all(b"a").unwrap();
+    assert_eq!(0xaf63_dc4c_8601_ec8c, hasher.finish());
+
+    // the bytes of an image, as hashed by the templates fingerprint
+    let mut hasher = StableHasher::default();
+    hasher.write_all(&(0..=255).collect_vec()).unwrap();
+    assert_eq!(0x4242_dc52_49c3_3625, hasher.finish());
+
+    assert_eq!(
+        0x2439_39fb_a236_42ba,
+        stable_hash(&(vec![0u8, 1, 2, 3], 4usize, "label"))
+    );
+
+    let user_settings = get_roster_user_settings(vec![
+        LabeledCombo::new(0, "First".into(), vec![0, 1, 2, 3]),
+        LabeledCombo::new(1, "Second".into(), vec![4, 5, 6, 7]),
+    ]);
+    assert_eq!(
+        0x6d52_ede1_ec19_9653,
+        get_settings_cache_key(&user_settings)
+    );
+    assert_eq!(
+        0x785e_34bf_6eab_cee8,
+        get_cache_key(&user_settings, &collection![0 => 1, 1 => 2], 3, &[4])
+    );
+}
@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    collection,
+    logic::Reward::*,
+    prices::{get_reward_values, parse_prices, PriceError, PriceFormat},
+};
+
+#[test]
+fn parses_json_prices() {
+    assert_eq!(
+        Ok(collection! {Scarab => 4.5, DivinationCard => 12.0}),
+        parse_prices(
+            r#"{"Scarab": 4.5, "divination card": 12}"#,
+            PriceFormat::Json
+        )
+    );
+    assert!(matches!(
+        parse_prices(r#"{"Scarab": "cheap"}"#, PriceFormat::Json),
+        Err(PriceError::MalformedJson(_))
+    ));
+}
+
+#[test]
+fn parses_csv_prices() {
+    assert_eq!(
+        Ok(collection! {Scarab => 4.5, Currency => 1.0}),
+        parse_prices(
+            "reward,chaos value\n# exported yesterday\n\nScarab, 4.5\ncurrency,1\n",
+            PriceFormat::Csv
+        )
+    );
+    assert_eq!(
+        Ok(collection! {Scarab => 4.5}),
+        parse_prices("Scarab,4.5", PriceFormat::Csv)
+    );
+    assert_eq!(
+        Err(PriceError::MalformedLine(3, "Currency;1".into())),
+        parse_prices(
+            "reward,chaos value\nScarab,4.5\nCurrency;1",
+            PriceFormat::Csv
+        )
+    );
+    assert_eq!(
+        Err(PriceError::MalformedLine(2, "Currency,lots".into())),
+        parse_prices("Scarab,4.5\nCurrency,lots", PriceFormat::Csv)
+    );
+}
+
+#[test]
+fn rejects_invalid_prices() {
+    assert_eq!(
+        Err(PriceError::UnknownReward("Mirror".into())),
+        parse_prices("Mirror,100000", PriceFormat::Csv)
+    );
+    assert_eq!(
+        Err(PriceError::InvalidPrice(Scarab, -1.0)),
+        parse_prices("Scarab,-1", PriceFormat::Csv)
+    );
+    assert_eq!(
+        Err(PriceError::DuplicatePrice(Scarab)),
+        parse_prices("Scarab,1\nscarab,2", PriceFormat::Csv)
+    );
+    assert_eq!(
+        Err(PriceError::Empty),
+        parse_prices("{}", PriceFormat::Json)
+    );
+    assert_eq!(
+        Err(PriceError::Empty),
+        parse_prices("reward,chaos value\n", PriceFormat::Csv)
+    );
+}
+
+#[test]
+fn scales_unpriced_rewards() {
+    // scarabs and currency are both worth 25 by default, so imported prices are on average twice as high
+    let reward_values = get_reward_values(&collection! {Scarab => 80.0, Currency => 20.0});
+    assert_eq!(80.0, reward_values[&Scarab]);
+    assert_eq!(20.0, reward_values[&Currency]);
+    assert_eq!(50.0, reward_values[&DivinationCard]);
+    assert_eq!(2.0, reward_values[&Generic]);
+
+    let reward_values = get_reward_values(&BTreeMap::new());
+    assert_eq!(25.0, reward_values[&Scarab]);
+    assert_eq!(1.0, reward_values[&Generic]);
+}
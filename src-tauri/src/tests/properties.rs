@@ -0,0 +1,196 @@
+use itertools::Itertools;
+use proptest::collection::{btree_set, vec};
+use proptest::prelude::*;
+use proptest::sample::{select, subsequence};
+use std::collections::{BTreeMap, HashSet};
+
+use crate::logic::{
+    get_effective_filler_count, get_produced_modifier_ids, suggest_combo, LabeledCombo, ModifierId,
+    SolverSettings, UserSettings, MODIFIERS, QUEUE_LENGTH, STASH_CAPACITY,
+};
+
+/// Each case runs the solver, which may take up to its whole time budget.
+const CASE_COUNT: u32 = 128;
+
+#[derive(Debug)]
+struct Case {
+    user_settings: UserSettings,
+    stash: BTreeMap<ModifierId, usize>,
+    stash_cell_count: usize,
+    queue: Vec<ModifierId>,
+}
+
+fn modifier_id() -> impl Strategy<Value = ModifierId> {
+    0..MODIFIERS.by_id.len() as ModifierId
+}
+
+fn distinct_modifier_ids(count: usize) -> impl Strategy<Value = Vec<ModifierId>> {
+    subsequence(MODIFIERS.by_id.keys().copied().collect_vec(), count).prop_shuffle()
+}
+
+fn combo() -> impl Strategy<Value = Vec<ModifierId>> {
+    (
+        distinct_modifier_ids(QUEUE_LENGTH),
+        proptest::bool::weighted(0.25),
+        0..QUEUE_LENGTH - 1,
+    )
+        .prop_map(|(mut combo, is_repeated, repeated_index)| {
+            // combos may use the same modifier more than once
+            if is_repeated {
+                combo[QUEUE_LENGTH - 1] = combo[repeated_index];
+            }
+
+            combo
+        })
+}
+
+/// Modifiers of the combos, or any modifier, so that the roster combos are often affordable.
+fn stash(combos: &[Vec<ModifierId>]) -> impl Strategy<Value = BTreeMap<ModifierId, usize>> {
+    let stash_modifier_id = if combos.is_empty() {
+        modifier_id().boxed()
+    } else {
+        prop_oneof![modifier_id(), select(combos.concat())].boxed()
+    };
+    prop_oneof![3 => 0..30usize, 1 => 60..65usize]
+        .prop_flat_map(move |stash_size| vec(stash_modifier_id.clone(), stash_size))
+        .prop_map(|modifier_ids| modifier_ids.into_iter().counts().into_iter().collect())
+}
+
+/// A prefix of one of the combos, or any modifiers.
+fn queue(combos: &[Vec<ModifierId>]) -> impl Strategy<Value = Vec<ModifierId>> {
+    let combos = combos.to_vec();
+    (0..QUEUE_LENGTH).prop_flat_map(move |queue_length| {
+        if combos.is_empty() {
+            distinct_modifier_ids(queue_length).boxed()
+        } else {
+            prop_oneof![
+                distinct_modifier_ids(queue_length),
+                select(combos.clone()).prop_map(move |combo| combo[..queue_length].to_vec())
+            ]
+            .boxed()
+        }
+    })
+}
+
+fn case() -> impl Strategy<Value = Case> {
+    vec(combo(), 0..4)
+        .prop_flat_map(|combos| {
+            (
+                stash(&combos),
+                queue(&combos),
+                btree_set(modifier_id(), 0..8),
+                50..STASH_CAPACITY,
+                1..4usize,
+                Just(combos),
+            )
+        })
+        .prop_map(
+            |(
+                stash,
+                queue,
+                forbidden_modifier_ids,
+                stash_threshold,
+                max_effective_fillers,
+                combos,
+            )| {
+                let combo_catalog = combos
+                    .into_iter()
+                    .enumerate()
+                    .map(|(combo_id, combo)| {
+                        LabeledCombo::new(combo_id as u64, String::new(), combo)
+                    })
+                    .collect_vec();
+                let combo_roster = combo_catalog.iter().map(|combo| combo.id).collect_vec();
+
+                // only modifiers unused by the roster may be forbidden, just like in the settings UI
+                let used_modifier_ids = combo_catalog
+                    .iter()
+                    .flat_map(|combo| {
+                        combo.combo.iter().flat_map(|modifier_id| {
+                            MODIFIERS.components[modifier_id]
+                                .keys()
+                                .chain(std::iter::once(modifier_id))
+                        })
+                    })
+                    .copied()
+                    .collect::<HashSet<_>>();
+                let forbidden_modifier_ids = forbidden_modifier_ids
+                    .into_iter()
+                    .filter(|modifier_id| !used_modifier_ids.contains(modifier_id))
+                    .collect();
+
+                Case {
+                    user_settings: UserSettings {
+                        combo_catalog,
+                        combo_roster,
+                        forbidden_modifier_ids,
+                        hotkey: "".into(),
+                        show_tiers: false,
+                        solver_settings: SolverSettings {
+                            stash_threshold,
+                            max_effective_fillers,
+                            ..SolverSettings::default()
+                        },
+                        manual_layout: None,
+                    },
+                    stash_cell_count: stash.values().sum(),
+                    stash,
+                    queue,
+                }
+            },
+        )
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASE_COUNT))]
+
+    #[test]
+    fn suggested_combos_are_valid(case in case()) {
+        let Case {
+            user_settings,
+            stash,
+            stash_cell_count,
+            queue,
+        } = case;
+        if let Some(suggestion) = suggest_combo(&user_settings, &stash, stash_cell_count, &queue) {
+            let combo = suggestion.combo;
+            prop_assert_eq!(QUEUE_LENGTH, combo.len());
+            prop_assert!(combo.starts_with(&queue));
+
+            let combo_suffix = &combo[queue.len()..];
+            for (modifier_id, count) in combo_suffix.iter().counts() {
+                prop_assert!(stash.get(modifier_id).copied().unwrap_or_default() >= count);
+                prop_assert!(!user_settings.forbidden_modifier_ids.contains(modifier_id));
+            }
+
+            let is_roster_combo = user_settings
+                .get_roster_combos()
+                .any(|roster_combo| roster_combo.combo == combo);
+            let solver_settings = &user_settings.solver_settings;
+            if !is_roster_combo && stash_cell_count < solver_settings.stash_threshold {
+                prop_assert!(
+                    get_effective_filler_count(&combo, &user_settings.get_filler_modifier_ids())
+                        <= solver_settings.max_effective_fillers
+                );
+            }
+        }
+    }
+
+    /// Includes unknown and duplicate ids on purpose; see also the `get_produced_modifier_ids` fuzz target.
+    #[test]
+    fn produced_modifiers_are_valid(
+        combo in vec(prop_oneof![modifier_id(), any::<ModifierId>()], 0..QUEUE_LENGTH * 2)
+    ) {
+        let produced_modifier_ids = get_produced_modifier_ids(&combo);
+
+        let mut used_modifier_ids = HashSet::new();
+        for (recipe, modifier_id) in &produced_modifier_ids {
+            prop_assert!(!recipe.is_empty());
+            prop_assert_eq!(&MODIFIERS.by_id[modifier_id].recipe, recipe);
+            prop_assert!(recipe.iter().all(|modifier_id| combo.contains(modifier_id)));
+            prop_assert!(recipe
+                .iter()
+                .all(|&modifier_id| used_modifier_ids.insert(modifier_id)));
+        }
+    }
+}
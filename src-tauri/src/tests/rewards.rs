@@ -0,0 +1,191 @@
+use std::collections::BTreeMap;
+
+use crate::{
+    collection,
+    logic::{get_combo_value, ModifierId, Reward::*},
+    rewards::{
+        estimate_combo_value, get_best_of_multiplier, get_expected_values, simulate_combo,
+        MonsterRewards, ValueEstimate,
+    },
+    utils::Rng,
+};
+
+const SAMPLE_COUNT: usize = 20_000;
+
+fn get_last_monster_rewards(combo: &[ModifierId]) -> MonsterRewards {
+    simulate_combo(combo).pop().unwrap()
+}
+
+fn assert_close(expected: f32, actual: f32) {
+    assert!(
+        (expected - actual).abs() < 1e-4,
+        "{} != {}",
+        expected,
+        actual
+    );
+}
+
+#[test]
+fn sums_rewards_of_the_same_type() {
+    // Toxic, Permafrost
+    assert_eq!(
+        MonsterRewards {
+            counts: collection! {Generic => 2, Gem => 1, Armour => 1},
+            reroll_count: 0,
+        },
+        get_last_monster_rewards(&[0, 3])
+    );
+}
+
+#[test]
+fn rerolls_accumulate() {
+    // Mirror Image
+    let rewards = get_last_monster_rewards(&[46]);
+    assert_eq!(2, rewards.reroll_count);
+    assert_close(25.0 * 1.5, rewards.get_expected_values()[&Scarab]);
+
+    // Necromancer, Mirror Image
+    let rewards = get_last_monster_rewards(&[31, 46]);
+    assert_eq!(
+        MonsterRewards {
+            counts: collection! {Generic => 1, Scarab => 1},
+            reroll_count: 4,
+        },
+        rewards
+    );
+    assert_close(
+        25.0 * get_best_of_multiplier(5),
+        rewards.get_expected_values()[&Scarab],
+    );
+}
+
+#[test]
+fn adds_a_reward_of_each_type() {
+    // Toxic, Lunaris-Touched
+    assert_eq!(
+        MonsterRewards {
+            counts: collection! {Gem => 2, Generic => 2, Unique => 2},
+            reroll_count: 0,
+        },
+        get_last_monster_rewards(&[0, 54])
+    );
+}
+
+#[test]
+fn doubles_rewards_after_additional_ones() {
+    // Toxic, Kitava-Touched
+    assert_eq!(
+        MonsterRewards {
+            counts: collection! {Gem => 2, Generic => 4},
+            reroll_count: 0,
+        },
+        get_last_monster_rewards(&[0, 62])
+    );
+
+    // Kitava-Touched, Lunaris-Touched
+    assert_eq!(
+        MonsterRewards {
+            counts: collection! {Generic => 4, Unique => 4},
+            reroll_count: 0,
+        },
+        get_last_monster_rewards(&[62, 54])
+    );
+}
+
+#[test]
+fn converts_rewards_in_queue_order() {
+    // Toxic, Arakaali-Touched, Frostweaver
+    assert_eq!(
+        MonsterRewards {
+            counts: collection! {DivinationCard => 3, Armour => 1},
+            reroll_count: 0,
+        },
+        get_last_monster_rewards(&[0, 56, 2])
+    );
+
+    // Arakaali-Touched, Shakari-Touched
+    assert_eq!(
+        MonsterRewards {
+            counts: collection! {Unique => 2},
+            reroll_count: 0,
+        },
+        get_last_monster_rewards(&[56, 60])
+    );
+}
+
+#[test]
+fn sums_expected_values_over_monsters() {
+    // Frostweaver, Ice Prison
+    let expected_values = get_expected_values(&[2, 40]);
+    assert_eq!(
+        vec![Armour],
+        expected_values.keys().copied().collect::<Vec<_>>()
+    );
+    assert_close(
+        1.0 + 3.0 * get_best_of_multiplier(2),
+        expected_values[&Armour],
+    );
+    assert_close(expected_values[&Armour], get_combo_value(&[2, 40]));
+    assert_eq!(BTreeMap::new(), get_expected_values(&[]));
+}
+
+#[test]
+fn estimates_are_deterministic() {
+    // Toxic, Mirror Image, Kitava-Touched
+    let combo = [0, 46, 62];
+    let estimate = estimate_combo_value(&combo, |_| true, 100, &mut Rng::new(42));
+    assert_eq!(
+        estimate,
+        estimate_combo_value(&combo, |_| true, 100, &mut Rng::new(42))
+    );
+    assert_ne!(
+        estimate,
+        estimate_combo_value(&combo, |_| true, 100, &mut Rng::new(43))
+    );
+    assert_eq!(
+        ValueEstimate::default(),
+        estimate_combo_value(&combo, |_| true, 0, &mut Rng::new(42))
+    );
+}
+
+#[test]
+fn estimates_converge_to_expected_values() {
+    // Toxic, Mirror Image, Kitava-Touched, Arakaali-Touched
+    let combo = [0, 46, 62, 56];
+    let estimate = estimate_combo_value(&combo, |_| true, SAMPLE_COUNT, &mut Rng::new(0));
+    let expected_value = get_combo_value(&combo);
+    assert!(
+        (estimate.mean - expected_value).abs() < 0.02 * expected_value,
+        "{:?} != {}",
+        estimate,
+        expected_value
+    );
+
+    let estimate = estimate_combo_value(
+        &combo,
+        |&reward| reward == Gem,
+        SAMPLE_COUNT,
+        &mut Rng::new(0),
+    );
+    let expected_value = get_expected_values(&combo)[&Gem];
+    assert!(
+        (estimate.mean - expected_value).abs() < 0.02 * expected_value,
+        "{:?} != {}",
+        estimate,
+        expected_value
+    );
+}
+
+#[test]
+fn rerolls_reduce_variance() {
+    // a single scarab has a variance of (2 * 25)^2 / 12
+    let estimate = estimate_combo_value(&[46], |_| true, SAMPLE_COUNT, &mut Rng::new(0));
+    let best_of_3_variance = 50.0 * 50.0 * 3.0 / (4.0 * 4.0 * 5.0);
+    assert!((estimate.variance - best_of_3_variance).abs() < 0.05 * best_of_3_variance);
+    assert!(estimate.variance < 50.0 * 50.0 / 12.0);
+
+    // Solaris-Touched, 2 scarabs without rerolls
+    let estimate = estimate_combo_value(&[55], |_| true, SAMPLE_COUNT, &mut Rng::new(0));
+    let variance = 2.0 * 50.0 * 50.0 / 12.0;
+    assert!((estimate.variance - variance).abs() < 0.05 * variance);
+}
@@ -0,0 +1,343 @@
+//! Recognition of synthetic captures: every one of them is derived from the single in-game `screenshot.png`, by
+//! cropping, resizing or drawing over it, so they don't cover other resolutions, UI themes or lighting as real
+//! captures would.
+
+use itertools::Itertools;
+use opencv::core::{Mat, Rect};
+use opencv::imgcodecs::{imread, IMREAD_COLOR};
+use opencv::imgproc::{cvt_color, COLOR_BGR2BGRA};
+use opencv::prelude::*;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use crate::{
+    cache::Cache,
+    collection,
+    image::{
+        get_calibration_cells, get_cells, get_layout, get_layout_calibration, match_cell,
+        process_mat, CellContent, ManualLayout, MatSync, ProcessImageResult, Rectangle, Screenshot,
+        Vec2, SHORTLIST_LENGTH,
+    },
+    logic::MODIFIERS,
+    utils::DiscSynchronized,
+};
+
+/// Labeled captures, relative to the resources directory.
+const MANIFEST_PATH: &str = "synthetic_captures/manifest.json";
+
+const UNKNOWN_CELL_LABEL: &str = "<unknown>";
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Manifest {
+    screenshots: Vec<SyntheticCapture>,
+}
+
+/// A capture, i.e. a screenshot optionally cropped to simulate a different resolution, with the expected layout
+/// and cell contents. Cells are labeled by modifier name, `null` meaning an empty cell.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SyntheticCapture {
+    name: String,
+    path: String,
+    crop: Option<Rectangle>,
+    /// UI scale of the screenshot; the layout of scaled screenshots can only be set manually.
+    #[serde(default = "default_scale_percent")]
+    scale_percent: u32,
+    stash_offset: Vec2,
+    queue_offset: Vec2,
+    stash: Vec<Vec<Option<String>>>,
+    queue: Vec<Option<String>>,
+}
+
+fn default_scale_percent() -> u32 {
+    100
+}
+
+impl SyntheticCapture {
+    fn get_manual_layout(&self) -> ManualLayout {
+        ManualLayout {
+            offsets: collection![
+                0 => self.stash_offset,
+                1 => self.queue_offset,
+            ],
+            scale_percent: self.scale_percent,
+        }
+    }
+
+    /// The manual layout, unless the layout can be detected automatically.
+    fn get_required_manual_layout(&self) -> Option<ManualLayout> {
+        if self.scale_percent == 100 {
+            None
+        } else {
+            Some(self.get_manual_layout())
+        }
+    }
+}
+
+fn get_resources_path() -> PathBuf {
+    [env!("CARGO_MANIFEST_DIR"), "src", "resources"]
+        .iter()
+        .collect()
+}
+
+fn load_manifest() -> Manifest {
+    let path = get_resources_path().join(MANIFEST_PATH);
+    serde_json::from_reader(BufReader::new(File::open(&path).unwrap()))
+        .unwrap_or_else(|err| panic!("invalid manifest {:?}: {}", path, err))
+}
+
+fn load_screenshot(capture: &SyntheticCapture) -> Mat {
+    let path = get_resources_path().join(&capture.path);
+    let screenshot = imread(path.to_str().unwrap(), IMREAD_COLOR).unwrap();
+    assert!(!screenshot.empty(), "missing screenshot {:?}", path);
+    match capture.crop {
+        Some(crop) => Mat::roi(
+            &screenshot,
+            Rect::new(
+                crop.x as i32,
+                crop.y as i32,
+                crop.width as i32,
+                crop.height as i32,
+            ),
+        )
+        .unwrap()
+        .try_clone()
+        .unwrap(),
+        None => screenshot,
+    }
+}
+
+/// The screenshot as captured by the app.
+fn load_bgra_screenshot(capture: &SyntheticCapture) -> Screenshot {
+    let mut screenshot_bgra = Mat::default();
+    cvt_color(
+        &load_screenshot(capture),
+        &mut screenshot_bgra,
+        COLOR_BGR2BGRA,
+        0,
+    )
+    .unwrap();
+    let screenshot_size = screenshot_bgra.size().unwrap();
+    Screenshot {
+        buffer: screenshot_bgra.data_bytes().unwrap().to_vec(),
+        width: screenshot_size.width as usize,
+        height: screenshot_size.height as usize,
+    }
+}
+
+/// The label of the cell in the manifest; unknown cells never match any label.
+fn get_cell_label(content: CellContent) -> Option<String> {
+    match content {
+        CellContent::Empty => None,
+        CellContent::Modifier(modifier_id) => Some(MODIFIERS.by_id[&modifier_id].name.clone()),
+        CellContent::Unknown => Some(UNKNOWN_CELL_LABEL.into()),
+    }
+}
+
+/// Compares the recognized cells with the expected ones, returning the number of correct cells and a
+/// description of each mismatch.
+fn compare_cells(capture: &SyntheticCapture, result: &ProcessImageResult) -> (usize, Vec<String>) {
+    // stash cells in row-major order, like in the manifest
+    let stash_cells = result
+        .stash_cells
+        .iter()
+        .sorted_by_key(|&(area, _)| (area.y, area.x))
+        .map(|(_, cell)| get_cell_label(cell.content))
+        .collect_vec();
+    let queue_cells = result
+        .queue_contents
+        .iter()
+        .map(|&content| get_cell_label(content))
+        .collect_vec();
+    assert_eq!(
+        capture.stash.iter().flatten().count(),
+        stash_cells.len(),
+        "{}",
+        capture.name
+    );
+    assert_eq!(capture.queue.len(), queue_cells.len(), "{}", capture.name);
+
+    let cells = capture
+        .stash
+        .iter()
+        .flatten()
+        .zip(&stash_cells)
+        .enumerate()
+        .map(|(index, cells)| (format!("stash[{}][{}]", index / 8, index % 8), cells))
+        .chain(
+            capture
+                .queue
+                .iter()
+                .zip(&queue_cells)
+                .enumerate()
+                .map(|(index, cells)| (format!("queue[{}]", index), cells)),
+        )
+        .collect_vec();
+    let mismatches = cells
+        .iter()
+        .filter(|(_, (expected, actual))| expected != actual)
+        .map(|(cell, (expected, actual))| {
+            format!("{}: expected {:?}, got {:?}", cell, expected, actual)
+        })
+        .collect_vec();
+    (cells.len() - mismatches.len(), mismatches)
+}
+
+fn check_screenshot(
+    cache: &mut Cache,
+    capture: &SyntheticCapture,
+    manual_layout: Option<&ManualLayout>,
+) -> Vec<String> {
+    let result = match process_mat(cache, load_screenshot(capture), manual_layout) {
+        Some(result) => result,
+        None => return vec![format!("{}: no layout found", capture.name)],
+    };
+
+    let mut errors = Vec::new();
+    let layout = (result.layout[&0], result.layout[&1]);
+    if layout != (capture.stash_offset, capture.queue_offset) {
+        errors.push(format!(
+            "{}: expected layout {:?}, got {:?}",
+            capture.name,
+            (capture.stash_offset, capture.queue_offset),
+            layout
+        ));
+    }
+
+    let (correct_count, mismatches) = compare_cells(capture, &result);
+    let cell_count = correct_count + mismatches.len();
+    println!(
+        "{}: {}/{} cells correct ({:.1}%)",
+        capture.name,
+        correct_count,
+        cell_count,
+        100.0 * correct_count as f32 / cell_count as f32
+    );
+    errors.extend(
+        mismatches
+            .into_iter()
+            .map(|mismatch| format!("{}: {}", capture.name, mismatch)),
+    );
+    errors
+}
+
+/// Run with `cargo test synthetic_captures -- --nocapture` to see the per-screenshot accuracy.
+#[test]
+fn recognizes_captures() {
+    let manifest = load_manifest();
+    assert!(!manifest.screenshots.is_empty());
+
+    // every screenshot with a cold cache first, then all of them again with a shared one
+    let mut errors = manifest
+        .screenshots
+        .iter()
+        .flat_map(|capture| {
+            check_screenshot(
+                &mut Cache::create_new(),
+                capture,
+                capture.get_required_manual_layout().as_ref(),
+            )
+        })
+        .collect_vec();
+    let mut cache = Cache::create_new();
+    for capture in &manifest.screenshots {
+        errors.extend(check_screenshot(
+            &mut cache,
+            capture,
+            capture.get_required_manual_layout().as_ref(),
+        ));
+    }
+
+    assert!(errors.is_empty(), "\n{}", errors.join("\n"));
+}
+
+#[test]
+fn recognizes_captures_at_manual_layout() {
+    let manifest = load_manifest();
+    let errors = manifest
+        .screenshots
+        .iter()
+        .flat_map(|capture| {
+            check_screenshot(
+                &mut Cache::create_new(),
+                capture,
+                Some(&capture.get_manual_layout()),
+            )
+        })
+        .collect_vec();
+
+    assert!(errors.is_empty(), "\n{}", errors.join("\n"));
+}
+
+/// The shortlist must not lose any cell recognized by matching every template.
+#[test]
+fn shortlist_matches_exhaustive_matching() {
+    let manifest = load_manifest();
+    let mismatches = manifest
+        .screenshots
+        .iter()
+        .filter(|capture| capture.scale_percent == 100)
+        .flat_map(|capture| {
+            let screenshot = MatSync(load_screenshot(capture));
+            let layout = get_layout(&mut Cache::create_new(), &screenshot).unwrap();
+            get_cells(&layout)
+                .into_iter()
+                .filter_map(|cell| {
+                    let expected = match_cell(&screenshot, &cell, MODIFIERS.by_id.len());
+                    let actual = match_cell(&screenshot, &cell, SHORTLIST_LENGTH);
+                    if expected == actual {
+                        None
+                    } else {
+                        Some(format!(
+                            "{}: {:?}: expected {:?}, got {:?}",
+                            capture.name, cell.area, expected, actual
+                        ))
+                    }
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+
+    assert!(mismatches.is_empty(), "\n{}", mismatches.join("\n"));
+}
+
+#[test]
+fn calibrates_layout_of_scaled_captures() {
+    let manifest = load_manifest();
+    for capture in &manifest.screenshots {
+        let layout_calibration = get_layout_calibration(load_bgra_screenshot(capture));
+        assert_eq!(
+            capture.scale_percent, layout_calibration.scale_percent,
+            "{}",
+            capture.name
+        );
+    }
+}
+
+#[test]
+fn calibration_leaves_out_empty_cells() {
+    let manifest = load_manifest();
+    for capture in &manifest.screenshots {
+        let calibration_cells = get_calibration_cells(
+            &mut Cache::create_new(),
+            load_bgra_screenshot(capture),
+            Some(&capture.get_manual_layout()),
+        )
+        .unwrap();
+        assert_eq!(
+            capture
+                .stash
+                .iter()
+                .flatten()
+                .chain(&capture.queue)
+                .flatten()
+                .count(),
+            calibration_cells.len(),
+            "{}",
+            capture.name
+        );
+    }
+}
@@ -0,0 +1,189 @@
+use dashmap::DashMap;
+use itertools::Itertools;
+use opencv::core::{Mat, Point, Rect, Scalar, Size, CV_8U};
+use opencv::imgcodecs::{imread, IMREAD_COLOR};
+use opencv::imgproc::{
+    cvt_color, put_text, resize, COLOR_BGR2GRAY, FONT_HERSHEY_PLAIN, INTER_LINEAR, LINE_8,
+};
+use opencv::prelude::*;
+use std::path::PathBuf;
+
+use crate::{
+    image::{
+        choose_modifier_template, get_template_hashes, read_count, save_user_templates,
+        CalibrationCell, MatSync, ModifierTemplate, Rectangle, MAX_IMAGE_HASH_DISTANCE,
+    },
+    logic::{ModifierId, MODIFIERS},
+};
+
+fn load_reference_image(modifier_id: ModifierId) -> Mat {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("src/resources/reference_images")
+        .join(format!("{}.png", MODIFIERS.by_id[&modifier_id].name));
+    imread(path.to_str().unwrap(), IMREAD_COLOR).unwrap()
+}
+
+/// Digits drawn white on black, like the stack counts of the game.
+fn get_digit_templates() -> DashMap<usize, MatSync> {
+    (0..10)
+        .map(|digit| {
+            let mut template = Mat::zeros(14, 9, CV_8U).unwrap().to_mat().unwrap();
+            put_text(
+                &mut template,
+                &digit.to_string(),
+                Point::new(0, 12),
+                FONT_HERSHEY_PLAIN,
+                1.0,
+                Scalar::all(255.0),
+                1,
+                LINE_8,
+                false,
+            )
+            .unwrap();
+            (digit, MatSync(template))
+        })
+        .collect()
+}
+
+/// The first stash cell of the screenshot, in grayscale, with the given stack count drawn in its count area.
+fn get_stacked_cell(count_area: Rectangle, count: &str) -> Mat {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/resources/screenshot.png");
+    let screenshot = imread(path.to_str().unwrap(), IMREAD_COLOR).unwrap();
+    let mut cell = Mat::default();
+    cvt_color(
+        &Mat::roi(&screenshot, Rect::new(117, 346, 54, 54)).unwrap(),
+        &mut cell,
+        COLOR_BGR2GRAY,
+        0,
+    )
+    .unwrap();
+
+    let digit_templates = get_digit_templates();
+    let mut count_image = Mat::zeros(count_area.height as i32, count_area.width as i32, CV_8U)
+        .unwrap()
+        .to_mat()
+        .unwrap();
+    for (index, digit) in count.chars().enumerate() {
+        let template = digit_templates
+            .get(&(digit.to_digit(10).unwrap() as usize))
+            .unwrap();
+        let template_size = template.size().unwrap();
+        template
+            .copy_to(
+                &mut Mat::roi(
+                    &count_image,
+                    Rect::new(
+                        1 + index as i32 * template_size.width,
+                        2,
+                        template_size.width,
+                        template_size.height,
+                    ),
+                )
+                .unwrap(),
+            )
+            .unwrap();
+    }
+    count_image
+        .copy_to(
+            &mut Mat::roi(
+                &cell,
+                Rect::new(
+                    count_area.x as i32,
+                    count_area.y as i32,
+                    count_area.width as i32,
+                    count_area.height as i32,
+                ),
+            )
+            .unwrap(),
+        )
+        .unwrap();
+    cell
+}
+
+#[test]
+fn reads_stack_counts() {
+    let count_area = Rectangle::new(0, 0, 27, 18);
+    let digit_templates = get_digit_templates();
+    for (count, expected_count) in [("7", 7), ("12", 12), ("10", 10), ("11", 11)] {
+        assert_eq!(
+            Some(expected_count),
+            read_count(
+                &get_stacked_cell(count_area, count),
+                count_area,
+                &digit_templates
+            ),
+            "{}",
+            count
+        );
+    }
+
+    // without digit templates, the cell counts as a single modifier
+    assert_eq!(
+        None,
+        read_count(
+            &get_stacked_cell(count_area, "12"),
+            count_area,
+            &DashMap::new()
+        )
+    );
+}
+
+#[test]
+fn templates_have_distinct_hashes() {
+    let template_hashes = get_template_hashes();
+    for ((&modifier_id1, hash1), (&modifier_id2, hash2)) in
+        template_hashes.iter().tuple_combinations()
+    {
+        assert!(
+            (hash1 ^ hash2).count_ones() > MAX_IMAGE_HASH_DISTANCE,
+            "{} and {} have similar hashes",
+            MODIFIERS.by_id[&modifier_id1].name,
+            MODIFIERS.by_id[&modifier_id2].name
+        );
+    }
+}
+
+#[test]
+fn prefers_user_templates_which_fit() {
+    let reference_image = load_reference_image(0);
+    let template_size = reference_image.size().unwrap();
+    let template = ModifierTemplate::new(reference_image);
+    let user_template = ModifierTemplate::new(load_reference_image(1));
+    assert!(std::ptr::eq(
+        &user_template,
+        choose_modifier_template(Some(&user_template), &template, template_size)
+    ));
+    assert!(std::ptr::eq(
+        &template,
+        choose_modifier_template(None, &template, template_size)
+    ));
+
+    // e.g. captured at a larger UI scale, which could not be matched against the cell
+    let mut large_image = Mat::default();
+    resize(
+        &load_reference_image(1),
+        &mut large_image,
+        Size::new(template_size.width + 8, template_size.height + 8),
+        0.0,
+        0.0,
+        INTER_LINEAR,
+    )
+    .unwrap();
+    let large_user_template = ModifierTemplate::new(large_image);
+    assert!(std::ptr::eq(
+        &template,
+        choose_modifier_template(Some(&large_user_template), &template, template_size)
+    ));
+}
+
+#[test]
+fn rejects_duplicate_template_labels() {
+    let cell = CalibrationCell {
+        tag: 0,
+        area: Rectangle::default(),
+        modifier_id: None,
+        image: vec![],
+    };
+    // rejected before anything is written
+    assert!(save_user_templates(vec![(0, &cell), (1, &cell), (0, &cell)]).is_err());
+}
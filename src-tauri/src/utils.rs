@@ -1,14 +1,18 @@
 use bincode::{deserialize_from, serialize_into};
+use dashmap::DashMap;
 use dirs::config_dir;
+use once_cell::sync::Lazy;
 use opencv::core::{Mat, Vector};
 use opencv::imgcodecs::imwrite;
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::{from_reader, to_writer_pretty};
+use std::collections::BTreeMap;
 use std::error::Error;
-use std::fs::{create_dir_all, File};
-use std::io::{BufReader, BufWriter};
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[macro_export]
 macro_rules! collection {
@@ -27,11 +31,140 @@ macro_rules! info_timed {
     ($context:literal, $expr:expr) => {{
         let timer = std::time::Instant::now();
         let value = $expr;
-        info!("{} took {:?}", $context, timer.elapsed());
+        let elapsed = timer.elapsed();
+        info!("{} took {:?}", $context, elapsed);
+        $crate::utils::record_timing($context, elapsed);
         value
     }};
 }
 
+/// Aggregated durations of the expressions measured by [`info_timed`] with the same context.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TimingStats {
+    pub count: u64,
+    pub total_micros: u64,
+    pub min_micros: u64,
+    pub max_micros: u64,
+}
+
+static TIMINGS: Lazy<DashMap<&'static str, TimingStats>> = Lazy::new(DashMap::new);
+
+pub fn record_timing(context: &'static str, duration: Duration) {
+    let micros = duration.as_micros() as u64;
+    let mut timing_stats = TIMINGS.entry(context).or_default();
+    timing_stats.min_micros = if timing_stats.count == 0 {
+        micros
+    } else {
+        timing_stats.min_micros.min(micros)
+    };
+    timing_stats.max_micros = timing_stats.max_micros.max(micros);
+    timing_stats.total_micros += micros;
+    timing_stats.count += 1;
+}
+
+pub fn get_timings() -> BTreeMap<&'static str, TimingStats> {
+    TIMINGS
+        .iter()
+        .map(|entry| (*entry.key(), *entry.value()))
+        .collect()
+}
+
+/// FNV-1a hasher; unlike `DefaultHasher`, its output is stable across builds, so it is safe to persist. It is fed
+/// explicit byte encodings rather than `Hash` impls, whose output may change between Rust versions.
+#[derive(Clone, Copy, Debug)]
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl StableHasher {
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Write for StableHasher {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hashes the bincode encoding of the value, which is fixed by the bincode version rather than the Rust one.
+pub fn stable_hash<T: Serialize + ?Sized>(value: &T) -> u64 {
+    let mut hasher = StableHasher::default();
+    serialize_into(&mut hasher, value).expect("writing to a hasher never fails");
+    hasher.finish()
+}
+
+/// Small, seedable pseudo random number generator (SplitMix64).
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self(seed)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut value = self.0;
+        value = (value ^ (value >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        value = (value ^ (value >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        value ^ (value >> 31)
+    }
+
+    /// Uniformly distributed value in `0..bound`.
+    pub fn next_below(&mut self, bound: usize) -> usize {
+        (self.next_u64() % bound as u64) as usize
+    }
+
+    /// Uniformly distributed value in `0.0..1.0`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Held open by the running app, so that the CLI doesn't write the files the app would overwrite.
+const INSTANCE_LOCK_FILE_NAME: &str = "archbroski\\instance.lock";
+
+/// Exclusive access to the files of the app, released when dropped or when the process dies. Only enforced on
+/// Windows, where the lock file is opened without sharing.
+pub struct InstanceLock {
+    _file: File,
+}
+
+impl InstanceLock {
+    /// Fails if another process, e.g. the running app, holds the lock.
+    pub fn acquire() -> Result<Self, Box<dyn Error>> {
+        let path = config_dir()
+            .ok_or("Cannot find home directory.")?
+            .join(INSTANCE_LOCK_FILE_NAME);
+        if let Some(parent) = path.parent() {
+            create_dir_all(parent)?;
+        }
+
+        let mut options = OpenOptions::new();
+        options.write(true).create(true);
+        #[cfg(target_os = "windows")]
+        std::os::windows::fs::OpenOptionsExt::share_mode(&mut options, 0);
+        Ok(Self {
+            _file: options.open(path)?,
+        })
+    }
+}
+
 pub trait DiscSynchronized: Sized + Serialize + DeserializeOwned {
     const FILE_NAME: &'static str;
 
@@ -1,8 +1,9 @@
 import React from 'react';
-import { Delete, Add, Error } from '@mui/icons-material';
-import { Dialog, DialogTitle, DialogContent, DialogContentText, DialogActions, Button, TableContainer, Table, TableHead, TableRow, TableCell, TableBody, TextField, Select, MenuItem, Typography, Box, IconButton, Tooltip, Zoom, Fade } from '@mui/material';
+import { invoke, clipboard } from '@tauri-apps/api';
+import { Delete, Add, Error, FileDownload, FileUpload } from '@mui/icons-material';
+import { Dialog, DialogTitle, DialogContent, DialogContentText, DialogActions, Button, TableContainer, Table, TableHead, TableRow, TableCell, TableBody, TextField, Select, MenuItem, Typography, Box, IconButton, Tooltip, Zoom, Fade, Checkbox } from '@mui/material';
 import { numberKeys } from '.';
-import { LabeledCombo, ModifiersContext, UserSettingsContext } from './Settings';
+import { LabeledCombo, ModifiersContext, UserSettings, UserSettingsContext } from './Settings';
 import WithLoading from './WithLoading';
 import { DialogTransition } from './ComboSettings';
 
@@ -43,11 +44,71 @@ const DeleteComboDialog = ({ combo, open, onClose }: DeleteComboDialogProps) =>
     );
 };
 
+type ImportCombosDialogProps = {
+    open: boolean;
+    onClose: () => void;
+};
+const ImportCombosDialog = ({ open, onClose }: ImportCombosDialogProps) => {
+    const [, setUserSettings] = React.useContext(UserSettingsContext)!;
+    const [data, setData] = React.useState('');
+    const [error, setError] = React.useState<string | undefined>(undefined);
+    const close = () => {
+        setData('');
+        setError(undefined);
+        onClose();
+    };
+    const importCombos = () => {
+        invoke<UserSettings>('import_combos', { data })
+            .then(userSettings => {
+                setUserSettings(userSettings);
+                close();
+            })
+            .catch(setError);
+    };
+    return (
+        <Dialog
+            TransitionComponent={DialogTransition}
+            keepMounted
+            fullWidth
+            open={open}
+            onClose={close}>
+            <DialogTitle>
+                Import combos
+            </DialogTitle>
+            <DialogContent>
+                <DialogContentText>
+                    Paste combos exported by <b>archbroski</b> (either as a share string or as JSON).
+                </DialogContentText>
+                <TextField
+                    variant='standard'
+                    fullWidth
+                    multiline
+                    maxRows={8}
+                    spellCheck={false}
+                    value={data}
+                    error={error !== undefined}
+                    helperText={error}
+                    onChange={(event) => { setData(event.target.value) }} />
+            </DialogContent>
+            <DialogActions>
+                <Button onClick={close}>
+                    Cancel
+                </Button>
+                <Button disabled={data.trim() === ''} onClick={importCombos}>
+                    Import
+                </Button>
+            </DialogActions>
+        </Dialog>
+    );
+};
+
 const ComboCatalog = () => {
     const [modifiers] = React.useContext(ModifiersContext)!;
     const [userSettings, setUserSettings] = React.useContext(UserSettingsContext)!;
     const [deleteComboDialogOpen, setDeleteComboDialogOpen] = React.useState(false);
     const [comboToDelete, setComboToDelete] = React.useState<LabeledCombo | undefined>(undefined);
+    const [importCombosDialogOpen, setImportCombosDialogOpen] = React.useState(false);
+    const [selectedComboIds, setSelectedComboIds] = React.useState<number[]>([]);
     const sortedModifierIds = React.useMemo(() => {
         return numberKeys(modifiers.byId).sort((modifierId1, modifierId2) => {
             const modifierName1 = modifiers.byId[modifierId1].name;
@@ -68,6 +129,10 @@ const ComboCatalog = () => {
                         id: 1 + Math.max(0, ...userSettings.comboCatalog.map(({ id }) => id)),
                         label: '',
                         combo: [4, 5, 7, 2],
+                        weight: 100,
+                        maxRuns: null,
+                        enabled: true,
+                        fillerTolerance: null,
                     }
                 ]
             };
@@ -86,11 +151,10 @@ const ComboCatalog = () => {
         setUserSettings(userSettings => {
             return {
                 ...userSettings,
-                comboCatalog: userSettings.comboCatalog.map(({ id, label: label_, combo }) => ({
-                    id,
-                    label: id !== comboId ? label_ : label,
-                    combo
-                }))
+                comboCatalog: userSettings.comboCatalog.map(combo => combo.id !== comboId ? combo : {
+                    ...combo,
+                    label
+                })
             };
         });
     };
@@ -98,15 +162,27 @@ const ComboCatalog = () => {
         setUserSettings(userSettings => {
             return {
                 ...userSettings,
-                comboCatalog: userSettings.comboCatalog.map(({ id, label, combo }) => ({
-                    id,
-                    label,
-                    combo: id !== comboId ? combo : combo.map((modifierId_, modifierIdIndex_) =>
+                comboCatalog: userSettings.comboCatalog.map(combo => combo.id !== comboId ? combo : {
+                    ...combo,
+                    combo: combo.combo.map((modifierId_, modifierIdIndex_) =>
                         modifierIdIndex_ === modifierIdIndex ? modifierId : modifierId_)
-                }))
+                })
             };
         });
     };
+    const toggleSelectedComboId = (comboId: number) => {
+        setSelectedComboIds(selectedComboIds => selectedComboIds.includes(comboId)
+            ? selectedComboIds.filter(comboId_ => comboId_ !== comboId)
+            : [...selectedComboIds, comboId]);
+    };
+    const exportCombos = () => {
+        const comboIds = userSettings.comboCatalog
+            .map(({ id }) => id)
+            .filter(comboId => selectedComboIds.length === 0 || selectedComboIds.includes(comboId));
+        invoke<string>('export_combos', { comboIds, format: 'Compact' })
+            .then(clipboard.writeText)
+            .catch(console.error);
+    };
     const initiateDeleteCombo = (combo: LabeledCombo) => {
         setComboToDelete(combo);
         setDeleteComboDialogOpen(true);
@@ -121,10 +197,12 @@ const ComboCatalog = () => {
         <WithLoading sx={{ width: 1, height: 1 }} loadSuccessful={(
             <Box sx={{ width: 1, height: 1 }}>
                 <DeleteComboDialog combo={comboToDelete} open={deleteComboDialogOpen} onClose={confirmed => confirmDeleteCombo(comboToDelete?.id!, confirmed ?? false)} />
+                <ImportCombosDialog open={importCombosDialogOpen} onClose={() => { setImportCombosDialogOpen(false) }} />
                 <TableContainer sx={{ width: 1, height: 550, overflow: 'overlay' }}>
                     <Table>
                         <TableHead>
                             <TableRow>
+                                <TableCell padding='checkbox'></TableCell>
                                 <TableCell>Name</TableCell>
                                 <TableCell>Modifier 1</TableCell>
                                 <TableCell>Modifier 2</TableCell>
@@ -138,6 +216,11 @@ const ComboCatalog = () => {
                                 const { id: comboId, label, combo } = labeledCombo;
                                 return (
                                     <TableRow key={comboId} sx={{ 'td, th': { border: 0 } }}>
+                                        <TableCell padding='checkbox'>
+                                            <Checkbox
+                                                checked={selectedComboIds.includes(comboId)}
+                                                onChange={() => { toggleSelectedComboId(comboId) }} />
+                                        </TableCell>
                                         <TableCell>
                                             <TextField
                                                 variant='standard'
@@ -189,11 +272,26 @@ const ComboCatalog = () => {
                         </TableBody>
                     </Table>
                 </TableContainer>
-                <Fade in={userSettings.comboCatalog.length < 8}>
-                    <IconButton sx={{ alignSelf: 'center' }} onClick={() => { addCombo(); }}>
-                        <Add />
-                    </IconButton>
-                </Fade>
+                <Box sx={{ display: 'flex' }}>
+                    <Fade in={userSettings.comboCatalog.length < 8}>
+                        <IconButton sx={{ alignSelf: 'center' }} onClick={() => { addCombo(); }}>
+                            <Add />
+                        </IconButton>
+                    </Fade>
+                    <Box sx={{ flexGrow: 1 }} />
+                    <Tooltip title='Import combos'>
+                        <span>
+                            <IconButton disabled={userSettings.comboCatalog.length >= 8} onClick={() => { setImportCombosDialogOpen(true) }}>
+                                <FileDownload />
+                            </IconButton>
+                        </span>
+                    </Tooltip>
+                    <Tooltip title={selectedComboIds.length === 0 ? 'Copy all combos to clipboard' : 'Copy selected combos to clipboard'}>
+                        <IconButton onClick={() => { exportCombos() }}>
+                            <FileUpload />
+                        </IconButton>
+                    </Tooltip>
+                </Box>
             </Box>
         )} />
     );
@@ -1,9 +1,11 @@
 import React from 'react';
+import { invoke } from '@tauri-apps/api';
 import { TransitionGroup } from 'react-transition-group';
 import { Lock, Star, SyncAlt } from '@mui/icons-material';
-import { Box, Typography, Chip, Divider, Zoom, FormControlLabel, Switch, Fade } from '@mui/material';
-import { Modifiers, LabeledCombo, UserSettingsContext, ModifiersContext } from './Settings';
+import { Box, Typography, Chip, Divider, Zoom, FormControlLabel, Switch, Fade, Dialog, DialogTitle, DialogContent, DialogActions, Button, TextField, Slider } from '@mui/material';
+import { Modifiers, LabeledCombo, UserSettings, UserSettingsContext, ModifiersContext, ComboConstraints, ComboRuns } from './Settings';
 import WithLoading from './WithLoading';
+import { DialogTransition } from './ComboSettings';
 import { numberKeys } from '.';
 import { DragDropContext, Droppable, Draggable, DropResult, DragStart } from 'react-beautiful-dnd';
 
@@ -15,9 +17,125 @@ const comboLabel = ({ label, id }: LabeledCombo) => {
 const getUsedModifierIds = (modifiers: Modifiers, modifierId: number): number[] =>
     [modifierId, ...modifiers.byId[modifierId].recipe.flatMap(modifierId_ => getUsedModifierIds(modifiers, modifierId_))];
 
+type ComboConstraintsDialogProps = {
+    combo?: LabeledCombo;
+    runs: number;
+    open: boolean;
+    onClose: () => void;
+    onResetRuns: (comboId: number) => void;
+};
+const ComboConstraintsDialog = ({ combo, runs, open, onClose, onResetRuns }: ComboConstraintsDialogProps) => {
+    const [userSettings, setUserSettings] = React.useContext(UserSettingsContext)!;
+    const [constraints, setConstraints] = React.useState<ComboConstraints | undefined>(undefined);
+    const [error, setError] = React.useState<string | undefined>(undefined);
+    React.useEffect(() => {
+        if (combo !== undefined) {
+            const { weight, maxRuns, enabled, fillerTolerance } = combo;
+            setConstraints({ weight, maxRuns, enabled, fillerTolerance });
+            setError(undefined);
+        }
+    }, [combo]);
+    const save = () => {
+        invoke<UserSettings>('set_combo_constraints', { comboId: combo!.id, constraints })
+            .then(userSettings => {
+                setUserSettings(userSettings);
+                onClose();
+            })
+            .catch(setError);
+    };
+    return (
+        <Dialog
+            TransitionComponent={DialogTransition}
+            keepMounted
+            fullWidth
+            open={open}
+            onClose={onClose}>
+            <DialogTitle>
+                {combo !== undefined && comboLabel(combo)}
+            </DialogTitle>
+            {constraints !== undefined && (
+                <DialogContent sx={{ display: 'flex', flexDirection: 'column' }}>
+                    <FormControlLabel
+                        control={<Switch checked={constraints.enabled} onChange={event => setConstraints({ ...constraints, enabled: event.target.checked })} />}
+                        label="Enabled" />
+                    <TextField
+                        type='number'
+                        variant='standard'
+                        label='Weight'
+                        helperText='Relative priority; 100 is the default, 200 ranks the second combo as high as the first one.'
+                        inputProps={{ min: 1 }}
+                        value={constraints.weight}
+                        onChange={event => setConstraints({ ...constraints, weight: Math.max(1, +event.target.value) })}
+                        sx={{ my: 1 }} />
+                    <TextField
+                        type='number'
+                        variant='standard'
+                        label='Max runs'
+                        helperText={`Run ${runs} time(s) so far; leave empty to run the combo indefinitely.`}
+                        inputProps={{ min: 1 }}
+                        value={constraints.maxRuns ?? ''}
+                        onChange={event => setConstraints({
+                            ...constraints,
+                            maxRuns: event.target.value === '' ? null : Math.max(1, +event.target.value)
+                        })}
+                        sx={{ my: 1 }} />
+                    <FormControlLabel
+                        control={<Switch
+                            checked={constraints.fillerTolerance !== null}
+                            onChange={event => setConstraints({ ...constraints, fillerTolerance: event.target.checked ? Math.min(2, userSettings.solverSettings.queueLength) : null })} />}
+                        label="Custom filler tolerance" />
+                    <Slider
+                        min={0}
+                        max={userSettings.solverSettings.queueLength}
+                        step={1}
+                        marks
+                        valueLabelDisplay='auto'
+                        disabled={constraints.fillerTolerance === null}
+                        value={constraints.fillerTolerance ?? Math.min(2, userSettings.solverSettings.queueLength)}
+                        onChange={(_, value) => setConstraints({ ...constraints, fillerTolerance: value as number })} />
+                    {error !== undefined && (
+                        <Typography variant='body2' color='error'>
+                            {error}
+                        </Typography>
+                    )}
+                </DialogContent>
+            )}
+            <DialogActions>
+                <Button disabled={runs === 0} onClick={() => onResetRuns(combo!.id)}>
+                    Reset runs
+                </Button>
+                <Box sx={{ flexGrow: 1 }} />
+                <Button onClick={onClose}>
+                    Cancel
+                </Button>
+                <Button onClick={save}>
+                    Save
+                </Button>
+            </DialogActions>
+        </Dialog>
+    );
+};
+
 const ComboRoster = () => {
     const [modifiers] = React.useContext(ModifiersContext)!;
     const [userSettings, setUserSettings] = React.useContext(UserSettingsContext)!;
+    const [comboRuns, setComboRuns] = React.useState<ComboRuns>({ runs: {}, pendingComboId: null });
+    const [editedComboId, setEditedComboId] = React.useState<number | undefined>(undefined);
+    React.useEffect(() => {
+        invoke<ComboRuns>('get_combo_runs').then(setComboRuns).catch(console.error);
+    }, []);
+    const resetComboRuns = (comboId: number) => {
+        invoke<ComboRuns>('reset_combo_runs', { comboId }).then(setComboRuns).catch(console.error);
+    };
+    const rosterComboLabel = (combo: LabeledCombo) => {
+        const runs = comboRuns.runs[combo.id] ?? 0;
+        return combo.maxRuns !== null
+            ? `${comboLabel(combo)} (${runs}/${combo.maxRuns})`
+            : comboLabel(combo);
+    };
+    const isComboPursued = (combo: LabeledCombo) => {
+        return combo.enabled && (combo.maxRuns === null || (comboRuns.runs[combo.id] ?? 0) < combo.maxRuns);
+    };
 
     const toggleForbiddenModifierId = (modifierId: number) => {
         setUserSettings(userSettings => {
@@ -115,6 +233,12 @@ const ComboRoster = () => {
     return (
         <WithLoading sx={{ width: 1, height: 1 }} loadSuccessful={(
             <Box sx={{ width: 1, height: 1, display: 'flex', flexDirection: 'column' }}>
+                <ComboConstraintsDialog
+                    combo={userSettings.comboCatalog.find(({ id }) => id === editedComboId)}
+                    runs={editedComboId !== undefined ? comboRuns.runs[editedComboId] ?? 0 : 0}
+                    open={editedComboId !== undefined}
+                    onClose={() => { setEditedComboId(undefined) }}
+                    onResetRuns={resetComboRuns} />
                 <Box sx={{ position: 'relative', width: 1, height: 300, display: 'flex' }}>
                     <SyncAlt color='primary' sx={{
                         position: 'absolute',
@@ -159,16 +283,20 @@ const ComboRoster = () => {
                                         sx={{ width: 150, height: 280, display: 'flex', flexDirection: 'column', alignItems: 'center' }}>
 
                                         {
-                                            userSettings.comboRoster.map((comboId, comboIndex) => (
-                                                <Draggable key={comboId} draggableId={'' + comboId} index={comboIndex}>
-                                                    {provided => (
-                                                        <Chip ref={provided.innerRef} {...provided.draggableProps} {...provided.dragHandleProps}
-                                                            size='small'
-                                                            label={comboLabel(userSettings.comboCatalog.find(({ id }) => id === comboId)!)}
-                                                            sx={{ width: 150, minHeight: 'min-content', my: 0.5 }} />
-                                                    )}
-                                                </Draggable>
-                                            ))
+                                            userSettings.comboRoster.map((comboId, comboIndex) => {
+                                                const combo = userSettings.comboCatalog.find(({ id }) => id === comboId)!;
+                                                return (
+                                                    <Draggable key={comboId} draggableId={'' + comboId} index={comboIndex}>
+                                                        {provided => (
+                                                            <Chip ref={provided.innerRef} {...provided.draggableProps} {...provided.dragHandleProps}
+                                                                size='small'
+                                                                label={rosterComboLabel(combo)}
+                                                                onClick={() => { setEditedComboId(comboId) }}
+                                                                sx={{ width: 150, minHeight: 'min-content', my: 0.5, opacity: isComboPursued(combo) ? 1 : 0.5 }} />
+                                                        )}
+                                                    </Draggable>
+                                                );
+                                            })
                                         }
                                         {provided.placeholder}
                                     </Box>
@@ -33,7 +33,8 @@ const HelpDialog = ({ open, onClose }: HelpDialogProps) => {
                 <DialogContentText>
                     On the catalog tab, you may define up to 8 combos. On the roster tab, select which ones you'd like to have
                     as active combos. When you activate <b>archbroski</b>, it'll try to progress towards the active ones;
-                    topmost with the highest, bottommost with the lowest priority.<br />
+                    topmost with the highest, bottommost with the lowest priority. Click an active combo to weigh it, to
+                    disable it, to stop it after a number of runs or to adjust how many fillers it tolerates.<br />
                     <br />
                     Modifiers not used by any of the active combos act as fillers, used to save stash space or to complement
                     recipes (to hit the zone cap of 4). You may also lock any of the unused modifiers; <b>archbroski</b> ignores
@@ -30,6 +30,9 @@ const Error = () => {
                     {errorMessage === 'failed_to_load_cache' && (
                         <span>Failed to access the cache in your home directory!</span>
                     )}
+                    {errorMessage === 'failed_to_load_combo_runs' && (
+                        <span>Failed to access your combo runs in your home directory!</span>
+                    )}
                 </Typography>
             </Box>
             <Toolbar sx={{ display: 'flex', justifyContent: 'flex-end', alignItems: 'center' }}>
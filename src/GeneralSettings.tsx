@@ -1,11 +1,183 @@
 import React from 'react';
-import { Box, Button, Typography } from '@mui/material';
-import { UserSettingsContext } from './Settings';
+import { invoke } from '@tauri-apps/api';
+import { Box, Button, Chip, MenuItem, Select, TextField, Typography } from '@mui/material';
+import { RiskPreference, SolverSettings, UserSettings, UserSettingsContext } from './Settings';
 import WithLoading from './WithLoading';
+import LayoutCalibration from './LayoutCalibration';
+import TemplateCalibration from './TemplateCalibration';
+
+type CacheStats = {
+    imageCount: number;
+    imageHits: number;
+    imageMisses: number;
+    comboCount: number;
+    comboHits: number;
+    comboMisses: number;
+    sizeOnDisk: number | null;
+};
+
+type PriceSnapshot = {
+    fileName: string;
+    modifiedAt: number;
+    prices: { [reward: string]: number };
+};
+
+type SolverSettingFieldProps = {
+    label: string;
+    field: keyof SolverSettings;
+    min: number;
+    max: number;
+    helperText: string;
+};
+
+const SolverSettingsForm = () => {
+    const [userSettings, setUserSettings] = React.useContext(UserSettingsContext)!;
+    const [solverSettings, setSolverSettings] = React.useState<SolverSettings>(userSettings.solverSettings);
+    const [error, setError] = React.useState<string | undefined>(undefined);
+    const [rewards, setRewards] = React.useState<string[]>([]);
+    React.useEffect(() => {
+        invoke<string[]>('get_rewards').then(setRewards).catch(console.error);
+    }, []);
+    React.useEffect(() => {
+        setSolverSettings(userSettings.solverSettings);
+    }, [userSettings.solverSettings]);
+    const targetedRewards = solverSettings.mode === 'Roster' ? [] : solverSettings.mode.TargetRewards;
+    const setTargetedRewards = (targetedRewards: string[]) => {
+        const newSolverSettings: SolverSettings = {
+            ...solverSettings,
+            mode: targetedRewards.length === 0 ? 'Roster' : { TargetRewards: targetedRewards }
+        };
+        setSolverSettings(newSolverSettings);
+        save(newSolverSettings);
+    };
+    const setRiskPreference = (riskPreference: RiskPreference) => {
+        const newSolverSettings: SolverSettings = { ...solverSettings, riskPreference };
+        setSolverSettings(newSolverSettings);
+        save(newSolverSettings);
+    };
+    const save = (solverSettings: SolverSettings) => {
+        invoke<UserSettings>('set_solver_settings', { solverSettings })
+            .then(userSettings => {
+                setError(undefined);
+                setUserSettings(userSettings);
+            })
+            .catch(setError);
+    };
+    const SolverSettingField = ({ label, field, min, max, helperText }: SolverSettingFieldProps) => (
+        <TextField
+            type='number'
+            variant='standard'
+            label={label}
+            helperText={helperText}
+            inputProps={{ min, max }}
+            value={solverSettings[field]}
+            onChange={event => setSolverSettings(solverSettings => ({ ...solverSettings, [field]: +event.target.value }))}
+            onBlur={() => save(solverSettings)}
+            sx={{ width: 200, mr: 2, mb: 1 }} />
+    );
+    return (
+        <Box sx={{ display: 'flex', flexDirection: 'column' }}>
+            <Typography variant='h6' sx={{ my: 1 }}>
+                Solver
+            </Typography>
+            <Box sx={{ display: 'flex', flexWrap: 'wrap' }}>
+                {SolverSettingField({ label: 'Stash threshold', field: 'stashThreshold', min: 1, max: 64, helperText: 'Stash size from which it is cleared more aggressively' })}
+                {SolverSettingField({ label: 'Tier 1 filler minimum', field: 'tier1FillerMinCount', min: 1, max: 64, helperText: 'Spare copies needed to use a tier 1 modifier as a filler' })}
+                {SolverSettingField({ label: 'Maximum fillers', field: 'maxEffectiveFillers', min: 0, max: solverSettings.queueLength, helperText: 'Unless set per combo' })}
+                {SolverSettingField({ label: 'Queue length', field: 'queueLength', min: 1, max: 4, helperText: 'Modifiers the queue holds, and of suggested custom combos' })}
+            </Box>
+            <Typography variant='body2' sx={{ my: 1 }}>
+                Target rewards (ignores the roster while any is selected)
+            </Typography>
+            <Select
+                multiple
+                variant='standard'
+                value={targetedRewards}
+                onChange={event => setTargetedRewards(event.target.value as string[])}
+                renderValue={selected => (
+                    <Box sx={{ display: 'flex', flexWrap: 'wrap' }}>
+                        {selected.map(reward => <Chip key={reward} size='small' label={reward} sx={{ mr: 0.5 }} />)}
+                    </Box>
+                )}
+                sx={{ width: 400 }}>
+                {rewards.map(reward => (
+                    <MenuItem key={reward} value={reward}>
+                        {reward}
+                    </MenuItem>
+                ))}
+            </Select>
+            <TextField
+                select
+                variant='standard'
+                label='Risk preference'
+                helperText='How combos targeting rewards account for the luck of their drops (only applies when targeting rewards)'
+                value={solverSettings.riskPreference}
+                onChange={event => setRiskPreference(event.target.value as RiskPreference)}
+                disabled={solverSettings.mode === 'Roster'}
+                sx={{ width: 400, mt: 1 }}>
+                <MenuItem value='Neutral'>Neutral (highest average value)</MenuItem>
+                <MenuItem value='Safe'>Safe (steadier value)</MenuItem>
+                <MenuItem value='HighVariance'>High variance (bigger jackpots)</MenuItem>
+            </TextField>
+            {error !== undefined && (
+                <Typography variant='body2' color='error'>
+                    {error}
+                </Typography>
+            )}
+        </Box>
+    );
+};
+
+const PricesSection = () => {
+    const [priceSnapshot, setPriceSnapshot] = React.useState<PriceSnapshot | null>(null);
+    const [error, setError] = React.useState<string | undefined>(undefined);
+    const onPriceSnapshot = (priceSnapshot: PriceSnapshot | null) => {
+        setError(undefined);
+        setPriceSnapshot(priceSnapshot);
+    };
+    const onError = (error: string) => {
+        setError(error);
+        setPriceSnapshot(null);
+    };
+    React.useEffect(() => {
+        invoke<PriceSnapshot | null>('get_price_snapshot').then(onPriceSnapshot).catch(onError);
+    }, []);
+    return (
+        <Box sx={{ display: 'flex', flexDirection: 'column' }}>
+            <Typography variant='h6' sx={{ my: 1 }}>
+                Prices
+            </Typography>
+            {priceSnapshot ? (
+                <Typography variant='body2'>
+                    {Object.keys(priceSnapshot.prices).length} rewards priced by {priceSnapshot.fileName}, as of {new Date(priceSnapshot.modifiedAt * 1000).toLocaleString()}
+                </Typography>
+            ) : (
+                <Typography variant='body2'>
+                    Default reward values. Drop a prices.json or prices.csv file in %APPDATA%\archbroski to use your own prices.
+                </Typography>
+            )}
+            {error !== undefined && (
+                <Typography variant='body2' color='error'>
+                    {error}
+                </Typography>
+            )}
+            <Button
+                variant='outlined'
+                sx={{ width: 200, mt: 1 }}
+                onClick={() => invoke<PriceSnapshot | null>('reload_prices').then(onPriceSnapshot).catch(onError)}>
+                Reload prices
+            </Button>
+        </Box>
+    );
+};
 
 const GeneralSettings = () => {
     const [userSettings, setUserSettings] = React.useContext(UserSettingsContext)!;
     const [recordingHotkey, setRecordingHotkey] = React.useState(false);
+    const [cacheStats, setCacheStats] = React.useState<CacheStats | undefined>(undefined);
+    React.useEffect(() => {
+        invoke<CacheStats>('get_cache_stats').then(setCacheStats).catch(console.error);
+    }, []);
     React.useEffect(() => {
         const keydownListener = (event: KeyboardEvent) => {
             event.preventDefault();
@@ -58,6 +230,26 @@ const GeneralSettings = () => {
                         </Button>
                     </Box>
                 </Box>
+                <SolverSettingsForm />
+                <PricesSection />
+                <LayoutCalibration />
+                <TemplateCalibration />
+                {cacheStats && (
+                    <Box sx={{ display: 'flex', flexDirection: 'column' }}>
+                        <Typography variant='h6' sx={{ my: 1 }}>
+                            Cache
+                        </Typography>
+                        <Typography variant='body2'>
+                            Images: {cacheStats.imageCount} ({cacheStats.imageHits} hits, {cacheStats.imageMisses} misses)
+                        </Typography>
+                        <Typography variant='body2'>
+                            Combos: {cacheStats.comboCount} ({cacheStats.comboHits} hits, {cacheStats.comboMisses} misses)
+                        </Typography>
+                        <Typography variant='body2'>
+                            Size on disk: {cacheStats.sizeOnDisk === null ? 'unknown' : `${(cacheStats.sizeOnDisk / 1024).toFixed(1)} KiB`}
+                        </Typography>
+                    </Box>
+                )}
             </Box>
         )} />
     )
@@ -0,0 +1,215 @@
+import React from 'react';
+import { invoke } from '@tauri-apps/api';
+import { Box, Button, Dialog, DialogActions, DialogContent, DialogTitle, Slider, Typography } from '@mui/material';
+import { ManualLayout, UserSettings, UserSettingsContext } from './Settings';
+import { DialogTransition } from './ComboSettings';
+
+type LayoutCandidate = {
+    tag: number;
+    area: { x: number, y: number, width: number, height: number };
+    score: number;
+};
+
+type LayoutCalibrationResult = {
+    image: number[];
+    width: number;
+    height: number;
+    scalePercent: number;
+    candidates: LayoutCandidate[];
+    threshold: number;
+};
+
+const CELL_GROUP_NAMES: { [tag: number]: string } = {
+    0: 'Stash',
+    1: 'Queue',
+};
+
+type Drag = {
+    tag: number;
+    clientX: number;
+    clientY: number;
+    offset: { x: number, y: number };
+};
+
+type LayoutDialogProps = {
+    calibration: LayoutCalibrationResult | undefined;
+    open: boolean;
+    onClose: () => void;
+};
+const LayoutDialog = ({ calibration, open, onClose }: LayoutDialogProps) => {
+    const [, setUserSettings] = React.useContext(UserSettingsContext)!;
+    const [offsets, setOffsets] = React.useState<ManualLayout['offsets']>({});
+    const [scalePercent, setScalePercent] = React.useState(100);
+    const [error, setError] = React.useState<string | undefined>(undefined);
+    const imageRef = React.useRef<HTMLImageElement | null>(null);
+    const dragRef = React.useRef<Drag | undefined>(undefined);
+    const imageUrl = React.useMemo(
+        () => calibration && URL.createObjectURL(new Blob([new Uint8Array(calibration.image)], { type: 'image/jpeg' })),
+        [calibration]);
+    React.useEffect(() => () => {
+        if (imageUrl !== undefined) {
+            URL.revokeObjectURL(imageUrl);
+        }
+    }, [imageUrl]);
+    React.useEffect(() => {
+        if (calibration !== undefined) {
+            setOffsets(Object.fromEntries(calibration.candidates.map(({ tag, area }) => [tag, { x: area.x, y: area.y }])));
+            setScalePercent(calibration.scalePercent);
+            setError(undefined);
+        }
+    }, [calibration]);
+    if (calibration === undefined) {
+        return null;
+    }
+
+    const startDrag = (tag: number) => (event: React.PointerEvent<HTMLDivElement>) => {
+        event.currentTarget.setPointerCapture(event.pointerId);
+        dragRef.current = { tag, clientX: event.clientX, clientY: event.clientY, offset: offsets[tag] };
+    };
+    const drag = (event: React.PointerEvent<HTMLDivElement>) => {
+        const dragState = dragRef.current;
+        if (dragState === undefined || imageRef.current === null) {
+            return;
+        }
+
+        // from displayed pixels to screenshot pixels
+        const ratio = calibration.width / imageRef.current.clientWidth;
+        setOffsets(offsets => ({
+            ...offsets,
+            [dragState.tag]: {
+                x: Math.max(0, Math.round(dragState.offset.x + (event.clientX - dragState.clientX) * ratio)),
+                y: Math.max(0, Math.round(dragState.offset.y + (event.clientY - dragState.clientY) * ratio)),
+            },
+        }));
+    };
+    const endDrag = () => {
+        dragRef.current = undefined;
+    };
+    const save = () => {
+        invoke<UserSettings>('set_manual_layout', { manualLayout: { offsets, scalePercent } })
+            .then(userSettings => {
+                setUserSettings(userSettings);
+                onClose();
+            })
+            .catch(setError);
+    };
+    return (
+        <Dialog
+            TransitionComponent={DialogTransition}
+            fullWidth
+            maxWidth='lg'
+            open={open}
+            onClose={onClose}>
+            <DialogTitle>
+                Confirm the layout
+            </DialogTitle>
+            <DialogContent>
+                <Typography variant='body2' sx={{ mb: 1 }}>
+                    Drag the stash and the queue rectangles over the game UI, and adjust the scale until their cells line up. Scores below {calibration.threshold} are too low for the automatic detection.
+                </Typography>
+                <Box sx={{ position: 'relative', userSelect: 'none' }}>
+                    <Box component='img' ref={imageRef} src={imageUrl} draggable={false} sx={{ display: 'block', width: '100%' }} />
+                    {calibration.candidates.map(({ tag, area, score }) => {
+                        const offset = offsets[tag] ?? { x: area.x, y: area.y };
+                        // the candidate areas are at the scale of the calibration
+                        const width = area.width * scalePercent / calibration.scalePercent;
+                        const height = area.height * scalePercent / calibration.scalePercent;
+                        return (
+                            <Box
+                                key={tag}
+                                onPointerDown={startDrag(tag)}
+                                onPointerMove={drag}
+                                onPointerUp={endDrag}
+                                sx={{
+                                    position: 'absolute',
+                                    left: `${100 * offset.x / calibration.width}%`,
+                                    top: `${100 * offset.y / calibration.height}%`,
+                                    width: `${100 * width / calibration.width}%`,
+                                    height: `${100 * height / calibration.height}%`,
+                                    border: 2,
+                                    borderColor: score > calibration.threshold ? 'success.main' : 'warning.main',
+                                    cursor: 'move',
+                                }}>
+                                <Typography variant='caption' sx={{ backgroundColor: 'background.paper', px: 0.5 }}>
+                                    {CELL_GROUP_NAMES[tag] ?? tag} ({score.toFixed(2)})
+                                </Typography>
+                            </Box>
+                        );
+                    })}
+                </Box>
+                <Typography variant='body2' sx={{ mt: 1 }}>
+                    Scale: {scalePercent}%
+                </Typography>
+                <Slider
+                    min={50}
+                    max={200}
+                    step={1}
+                    valueLabelDisplay='auto'
+                    value={scalePercent}
+                    onChange={(_, value) => setScalePercent(value as number)} />
+                {error !== undefined && (
+                    <Typography variant='body2' color='error'>
+                        {error}
+                    </Typography>
+                )}
+            </DialogContent>
+            <DialogActions>
+                <Button onClick={onClose}>Cancel</Button>
+                <Button onClick={save}>Save layout</Button>
+            </DialogActions>
+        </Dialog>
+    );
+};
+
+const LayoutCalibration = () => {
+    const [userSettings, setUserSettings] = React.useContext(UserSettingsContext)!;
+    const [calibration, setCalibration] = React.useState<LayoutCalibrationResult | undefined>(undefined);
+    const [capturing, setCapturing] = React.useState(false);
+    const [dialogOpen, setDialogOpen] = React.useState(false);
+    const [error, setError] = React.useState<string | undefined>(undefined);
+    const capture = () => {
+        setCapturing(true);
+        setError(undefined);
+        invoke<LayoutCalibrationResult>('capture_layout_calibration')
+            .then(calibration => {
+                setCalibration(calibration);
+                setDialogOpen(true);
+            })
+            .catch(setError)
+            .finally(() => setCapturing(false));
+    };
+    const reset = () => {
+        invoke<UserSettings>('set_manual_layout', { manualLayout: null }).then(setUserSettings).catch(setError);
+    };
+    return (
+        <Box sx={{ display: 'flex', flexDirection: 'column' }}>
+            <Typography variant='h6' sx={{ my: 1 }}>
+                Layout
+            </Typography>
+            <Typography variant='body2'>
+                {userSettings.manualLayout === null
+                    ? 'Automatic detection. If the stash or the queue aren\'t found, set their position yourself: open the stash in game within 3 seconds of pressing Calibrate.'
+                    : `Manual layout at ${userSettings.manualLayout.scalePercent}% scale.`}
+            </Typography>
+            {error !== undefined && (
+                <Typography variant='body2' color='error'>
+                    {error}
+                </Typography>
+            )}
+            <Box sx={{ display: 'flex', mt: 1 }}>
+                <Button variant='outlined' sx={{ width: 200, mr: 2 }} disabled={capturing} onClick={capture}>
+                    {capturing ? 'Capturing...' : 'Calibrate'}
+                </Button>
+                <Button variant='outlined' sx={{ width: 200 }} disabled={userSettings.manualLayout === null} onClick={reset}>
+                    Use automatic detection
+                </Button>
+            </Box>
+            <LayoutDialog
+                calibration={calibration}
+                open={dialogOpen}
+                onClose={() => setDialogOpen(false)} />
+        </Box>
+    );
+};
+
+export default LayoutCalibration;
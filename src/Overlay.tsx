@@ -1,8 +1,9 @@
 import React from 'react';
 import { window, invoke } from '@tauri-apps/api';
 import { Box } from '@mui/system';
-import { CircularProgress, Fade, Grow } from '@mui/material';
+import { CircularProgress, Fade, Grow, Typography } from '@mui/material';
 import { Check, Error, Help } from '@mui/icons-material';
+import { Modifiers } from './Settings';
 
 type Rectangle = {
     x: number;
@@ -11,6 +12,22 @@ type Rectangle = {
     height: number;
 };
 
+type Hint = {
+    BuildsToward: {
+        modifierId: number;
+        label: string;
+    };
+} | {
+    Reserved: {
+        modifierId: number;
+        count: number;
+    };
+} | {
+    ClearingStash: {
+        freedCells: number;
+    };
+};
+
 type State = {
     type: 'Hidden';
 } | {
@@ -19,6 +36,8 @@ type State = {
     type: 'Computed';
     stashArea: Rectangle;
     suggestedCellArea: Rectangle;
+    hints: Hint[];
+    unknownCellCount: number;
 } | {
     type: 'DetectionError';
 } | {
@@ -27,7 +46,11 @@ type State = {
 
 const Overlay = () => {
     const [state, setState] = React.useState<State>({ type: 'Hidden' });
+    const [modifiers, setModifiers] = React.useState<Modifiers | null>(null);
     const canvasRef = React.useRef<HTMLCanvasElement | null>(null);
+    React.useEffect(() => {
+        invoke<Modifiers>('get_modifiers').then(setModifiers).catch(console.error);
+    }, []);
     React.useEffect(() => {
         const unlisten = window.getCurrent().listen('tauri://blur', () => {
             setState(state_ => {
@@ -189,6 +212,46 @@ const Overlay = () => {
                     }
                 </Grow>
             </Box>
+            <Box sx={{
+                position: 'fixed',
+                left: '50%',
+                bottom: 40,
+                transform: 'translate(-50%, 0)'
+            }}>
+                <Fade in={state.type === 'Computed' && (state.hints.length > 0 || state.unknownCellCount > 0)} timeout={500} style={{ zIndex: 2 }}>
+                    {
+                        <Box sx={{
+                            px: 2,
+                            py: 0.5,
+                            backgroundColor: 'rgba(0, 0, 0, 0.9)',
+                            borderRadius: 1
+                        }}>
+                            {state.type === 'Computed' && state.hints.map(hint => 'BuildsToward' in hint
+                                ? (
+                                    <Typography key={`builds-${hint.BuildsToward.modifierId}`} sx={{ color: 'white' }}>
+                                        Builds {modifiers?.byId[hint.BuildsToward.modifierId]?.name ?? 'an intermediate'} toward {hint.BuildsToward.label}
+                                    </Typography>
+                                )
+                                : 'Reserved' in hint
+                                    ? (
+                                        <Typography key={`reserved-${hint.Reserved.modifierId}`} sx={{ color: 'white' }}>
+                                            Keeps {hint.Reserved.count}× {modifiers?.byId[hint.Reserved.modifierId]?.name ?? 'a modifier'} for the roster
+                                        </Typography>
+                                    )
+                                    : (
+                                        <Typography key='clearing' sx={{ color: 'white' }}>
+                                            Stash full, clearing mode: frees {hint.ClearingStash.freedCells} cells
+                                        </Typography>
+                                    ))}
+                            {state.type === 'Computed' && state.unknownCellCount > 0 && (
+                                <Typography sx={{ color: 'orange' }}>
+                                    {state.unknownCellCount} stash cells weren't recognized and were left out
+                                </Typography>
+                            )}
+                        </Box>
+                    }
+                </Fade>
+            </Box>
             <canvas ref={canvasRef} />
         </div >
     )
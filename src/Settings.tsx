@@ -28,14 +28,45 @@ export type LabeledCombo = {
     id: number,
     label: string,
     combo: number[];
+    weight: number;
+    maxRuns: number | null;
+    enabled: boolean;
+    fillerTolerance: number | null;
 }
 
+export type ComboConstraints = Pick<LabeledCombo, 'weight' | 'maxRuns' | 'enabled' | 'fillerTolerance'>;
+
+export type ComboRuns = {
+    runs: { [key: number]: number };
+    pendingComboId: number | null;
+};
+
+export type SolverMode = 'Roster' | { TargetRewards: string[] };
+
+export type RiskPreference = 'Neutral' | 'Safe' | 'HighVariance';
+
+export type SolverSettings = {
+    stashThreshold: number;
+    tier1FillerMinCount: number;
+    maxEffectiveFillers: number;
+    queueLength: number;
+    mode: SolverMode;
+    riskPreference: RiskPreference;
+};
+
+export type ManualLayout = {
+    offsets: { [tag: number]: { x: number, y: number } };
+    scalePercent: number;
+};
+
 export type UserSettings = {
     comboCatalog: LabeledCombo[];
     comboRoster: number[];
     forbiddenModifierIds: number[];
     hotkey: string;
     showTiers: boolean;
+    solverSettings: SolverSettings;
+    manualLayout: ManualLayout | null;
 };
 
 const defaultUserSettings: UserSettings = {
@@ -44,6 +75,15 @@ const defaultUserSettings: UserSettings = {
     forbiddenModifierIds: [],
     hotkey: '',
     showTiers: false,
+    solverSettings: {
+        stashThreshold: 60,
+        tier1FillerMinCount: 3,
+        maxEffectiveFillers: 2,
+        queueLength: 4,
+        mode: 'Roster',
+        riskPreference: 'Neutral',
+    },
+    manualLayout: null,
 };
 
 export const ModifiersContext = React.createContext<[Modifiers] | undefined>(undefined);
@@ -0,0 +1,150 @@
+import React from 'react';
+import { invoke } from '@tauri-apps/api';
+import { Box, Button, Dialog, DialogActions, DialogContent, DialogTitle, MenuItem, Select, Typography } from '@mui/material';
+import { ModifiersContext } from './Settings';
+import { DialogTransition } from './ComboSettings';
+
+type CalibrationCell = {
+    tag: number;
+    area: { x: number, y: number, width: number, height: number };
+    modifierId: number | null;
+    image: number[];
+};
+
+type CalibrationDialogProps = {
+    cells: CalibrationCell[];
+    open: boolean;
+    onClose: () => void;
+    onSaved: (userTemplates: number[]) => void;
+};
+const CalibrationDialog = ({ cells, open, onClose, onSaved }: CalibrationDialogProps) => {
+    const [modifiers] = React.useContext(ModifiersContext)!;
+    const [labels, setLabels] = React.useState<(number | null)[]>([]);
+    const [error, setError] = React.useState<string | undefined>(undefined);
+    const imageUrls = React.useMemo(
+        () => cells.map(({ image }) => URL.createObjectURL(new Blob([new Uint8Array(image)], { type: 'image/png' }))),
+        [cells]);
+    React.useEffect(() => () => imageUrls.forEach(URL.revokeObjectURL), [imageUrls]);
+    React.useEffect(() => {
+        setLabels(cells.map(({ modifierId }) => modifierId));
+        setError(undefined);
+    }, [cells]);
+    const save = () => {
+        invoke<number[]>('save_user_templates', { labels })
+            .then(userTemplates => {
+                onSaved(userTemplates);
+                onClose();
+            })
+            .catch(setError);
+    };
+    const sortedModifiers = Object.values(modifiers.byId).sort((modifier1, modifier2) => modifier1.name.localeCompare(modifier2.name));
+    return (
+        <Dialog
+            TransitionComponent={DialogTransition}
+            fullWidth
+            maxWidth='lg'
+            open={open}
+            onClose={onClose}>
+            <DialogTitle>
+                Label the captured cells
+            </DialogTitle>
+            <DialogContent>
+                <Typography variant='body2' sx={{ mb: 1 }}>
+                    Each labeled cell becomes the template of its modifier. Cells which weren't recognized are outlined; empty cells are left out.
+                </Typography>
+                <Box sx={{ display: 'flex', flexWrap: 'wrap' }}>
+                    {cells.map((cell, index) => (
+                        <Box key={index} sx={{ display: 'flex', alignItems: 'center', width: 260, mr: 1, mb: 1 }}>
+                            <Box
+                                component='img'
+                                src={imageUrls[index]}
+                                sx={{ width: 40, height: 40, mr: 1, outline: cell.modifierId === null ? 2 : 0, outlineColor: 'warning.main' }} />
+                            <Select
+                                variant='standard'
+                                value={labels[index] ?? ''}
+                                displayEmpty
+                                onChange={event => setLabels(labels => labels.map((label, index_) =>
+                                    index_ === index ? (event.target.value === '' ? null : +event.target.value) : label))}
+                                sx={{ flexGrow: 1 }}>
+                                <MenuItem value=''>
+                                    <em>Skip</em>
+                                </MenuItem>
+                                {sortedModifiers.map(modifier => (
+                                    <MenuItem key={modifier.id} value={modifier.id}>
+                                        {modifier.name}
+                                    </MenuItem>
+                                ))}
+                            </Select>
+                        </Box>
+                    ))}
+                </Box>
+                {error !== undefined && (
+                    <Typography variant='body2' color='error'>
+                        {error}
+                    </Typography>
+                )}
+            </DialogContent>
+            <DialogActions>
+                <Button onClick={onClose}>Cancel</Button>
+                <Button onClick={save}>Save templates</Button>
+            </DialogActions>
+        </Dialog>
+    );
+};
+
+const TemplateCalibration = () => {
+    const [userTemplates, setUserTemplates] = React.useState<number[]>([]);
+    const [cells, setCells] = React.useState<CalibrationCell[]>([]);
+    const [capturing, setCapturing] = React.useState(false);
+    const [dialogOpen, setDialogOpen] = React.useState(false);
+    const [error, setError] = React.useState<string | undefined>(undefined);
+    React.useEffect(() => {
+        invoke<number[]>('get_user_templates').then(setUserTemplates).catch(console.error);
+    }, []);
+    const capture = () => {
+        setCapturing(true);
+        setError(undefined);
+        invoke<CalibrationCell[]>('capture_calibration_cells')
+            .then(cells => {
+                setCells(cells);
+                setDialogOpen(true);
+            })
+            .catch(setError)
+            .finally(() => setCapturing(false));
+    };
+    const clear = () => {
+        invoke<number[]>('clear_user_templates').then(setUserTemplates).catch(setError);
+    };
+    return (
+        <Box sx={{ display: 'flex', flexDirection: 'column' }}>
+            <Typography variant='h6' sx={{ my: 1 }}>
+                Templates
+            </Typography>
+            <Typography variant='body2'>
+                {userTemplates.length === 0
+                    ? 'Built-in templates. If modifiers are misdetected (e.g. because of Reshade or game filters), capture your own: open the stash in game within 3 seconds of pressing Calibrate.'
+                    : `${userTemplates.length} modifiers use your own templates.`}
+            </Typography>
+            {error !== undefined && (
+                <Typography variant='body2' color='error'>
+                    {error}
+                </Typography>
+            )}
+            <Box sx={{ display: 'flex', mt: 1 }}>
+                <Button variant='outlined' sx={{ width: 200, mr: 2 }} disabled={capturing} onClick={capture}>
+                    {capturing ? 'Capturing...' : 'Calibrate'}
+                </Button>
+                <Button variant='outlined' sx={{ width: 200 }} disabled={userTemplates.length === 0} onClick={clear}>
+                    Reset templates
+                </Button>
+            </Box>
+            <CalibrationDialog
+                cells={cells}
+                open={dialogOpen}
+                onClose={() => setDialogOpen(false)}
+                onSaved={setUserTemplates} />
+        </Box>
+    );
+};
+
+export default TemplateCalibration;